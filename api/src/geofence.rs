@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{DataRef, GlobalLocation, Location, TimedLocation};
+
+/// A named zone, evaluated against either the global or the site-local locations.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Geofence {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Coordinates of the points; `[longitude, latitude]` if global, `[x, y]` if local
+    #[serde(default)]
+    pub coordinates: Coordinates,
+    pub shape: GeofenceShape,
+    /// Emits a dwell event after staying in the zone for the given seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dwell_sec: Option<f64>,
+    /// Emits an exit event only after leaving the zone farther than the given meters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hysteresis_m: Option<f64>,
+}
+
+impl Geofence {
+    /// Returns whether the location is within the zone, including the boundary.
+    pub fn contains(&self, location: &Location) -> bool {
+        self.distance_m(location) == 0.0
+    }
+
+    /// Returns the distance from the zone as meters; zero if within the zone,
+    /// or NaN if the location is unknown.
+    pub fn distance_m(&self, location: &Location) -> f64 {
+        let point = match self.coordinates {
+            Coordinates::Global => [location.global.longitude, location.global.latitude],
+            Coordinates::Local => [location.local.x, location.local.y],
+        };
+        if !point.iter().all(|value| value.is_finite()) {
+            return f64::NAN;
+        }
+
+        // the offset of the point from the origin of the zone, as meters
+        let offset_m = |[x, y]: [f64; 2]| match self.coordinates {
+            Coordinates::Global => {
+                let origin = GlobalLocation {
+                    error_m: 0.0,
+                    latitude: location.global.latitude,
+                    longitude: location.global.longitude,
+                };
+                let (east, north) = origin.offset_m(&GlobalLocation {
+                    error_m: 0.0,
+                    latitude: y,
+                    longitude: x,
+                });
+                [east, north]
+            }
+            Coordinates::Local => [x - point[0], y - point[1]],
+        };
+
+        match &self.shape {
+            GeofenceShape::Circle { center, radius_m } => {
+                let distance_m = match self.coordinates {
+                    Coordinates::Global => GlobalLocation {
+                        error_m: 0.0,
+                        latitude: center[1],
+                        longitude: center[0],
+                    }
+                    .distance_m(&location.global),
+                    Coordinates::Local => (point[0] - center[0]).hypot(point[1] - center[1]),
+                };
+                (distance_m - radius_m).max(0.0)
+            }
+            GeofenceShape::Polygon { points } => {
+                // place the point at the origin
+                let points: Vec<_> = points.iter().copied().map(offset_m).collect();
+                if contains_polygon(&points, [0.0, 0.0]) {
+                    0.0
+                } else {
+                    distance_polygon(&points)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Coordinates {
+    #[default]
+    Global,
+    Local,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase", tag = "type")]
+pub enum GeofenceShape {
+    Circle {
+        center: [f64; 2],
+        radius_m: f64,
+    },
+    /// A simple polygon; the last point is connected to the first one
+    Polygon {
+        points: Vec<[f64; 2]>,
+    },
+}
+
+/// The geofences which an object is in.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GeofenceMembership {
+    pub data: DataRef,
+    pub location: TimedLocation,
+    pub geofences: Vec<String>,
+}
+
+/// A geofence event of an object.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GeofenceEvent {
+    pub kind: GeofenceEventKind,
+    pub geofence: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    pub data: DataRef,
+    pub location: TimedLocation,
+    /// When the object has entered the zone
+    pub entered_at: DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GeofenceEventKind {
+    Enter,
+    Exit,
+    /// The object has stayed in the zone longer than `dwell_sec`
+    Dwell,
+}
+
+/// Casts a ray to the east, treating the coordinates as planar.
+fn contains_polygon(points: &[[f64; 2]], [x, y]: [f64; 2]) -> bool {
+    let mut inside = false;
+    for (index, &[x1, y1]) in points.iter().enumerate() {
+        let [x2, y2] = points[(index + 1) % points.len()];
+
+        // on the boundary
+        let cross = (x2 - x1) * (y - y1) - (y2 - y1) * (x - x1);
+        if cross == 0.0 && x1.min(x2) <= x && x <= x1.max(x2) && y1.min(y2) <= y && y <= y1.max(y2)
+        {
+            return true;
+        }
+
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Returns the distance from the origin to the edges of the polygon.
+fn distance_polygon(points: &[[f64; 2]]) -> f64 {
+    points
+        .iter()
+        .enumerate()
+        .map(|(index, &[x1, y1])| {
+            let [x2, y2] = points[(index + 1) % points.len()];
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let length = dx * dx + dy * dy;
+            let t = if length > 0.0 {
+                (-(x1 * dx + y1 * dy) / length).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            (x1 + t * dx).hypot(y1 + t * dy)
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use crate::{LocalLocation, EARTH_RADIUS_M};
+
+    use super::*;
+
+    fn geofence(coordinates: Coordinates, shape: GeofenceShape) -> Geofence {
+        Geofence {
+            name: "zone".into(),
+            labels: Default::default(),
+            coordinates,
+            shape,
+            dwell_sec: None,
+            hysteresis_m: None,
+        }
+    }
+
+    fn global(longitude: f64, latitude: f64) -> Location {
+        Location {
+            global: GlobalLocation {
+                error_m: 0.0,
+                latitude,
+                longitude,
+            },
+            local: LocalLocation {
+                x: f64::NAN,
+                y: f64::NAN,
+                error_m: f64::NAN,
+            },
+            motion: Default::default(),
+        }
+    }
+
+    fn local(x: f64, y: f64) -> Location {
+        Location {
+            global: GlobalLocation {
+                error_m: f64::NAN,
+                latitude: f64::NAN,
+                longitude: f64::NAN,
+            },
+            local: LocalLocation { x, y, error_m: 0.0 },
+            motion: Default::default(),
+        }
+    }
+
+    /// A U-shaped zone, open to the north between `x = 3` and `x = 7`.
+    fn concave() -> Geofence {
+        geofence(
+            Coordinates::Local,
+            GeofenceShape::Polygon {
+                points: vec![
+                    [0.0, 0.0],
+                    [10.0, 0.0],
+                    [10.0, 10.0],
+                    [7.0, 10.0],
+                    [7.0, 3.0],
+                    [3.0, 3.0],
+                    [3.0, 10.0],
+                    [0.0, 10.0],
+                ],
+            },
+        )
+    }
+
+    fn square() -> Geofence {
+        geofence(
+            Coordinates::Global,
+            GeofenceShape::Polygon {
+                points: vec![
+                    [127.0, 37.0],
+                    [127.01, 37.0],
+                    [127.01, 37.01],
+                    [127.0, 37.01],
+                ],
+            },
+        )
+    }
+
+    #[test]
+    fn contain_in_concave_polygon() {
+        let geofence = concave();
+
+        assert!(geofence.contains(&local(1.0, 5.0)));
+        assert!(geofence.contains(&local(9.0, 9.0)));
+        assert!(geofence.contains(&local(5.0, 1.0)));
+        // within the notch
+        assert!(!geofence.contains(&local(5.0, 5.0)));
+        assert_eq!(geofence.distance_m(&local(5.0, 5.0)), 2.0);
+    }
+
+    #[test]
+    fn contain_on_boundary() {
+        let geofence = concave();
+
+        // on the edges
+        assert!(geofence.contains(&local(5.0, 0.0)));
+        assert!(geofence.contains(&local(3.0, 6.0)));
+        // on the vertices
+        assert!(geofence.contains(&local(0.0, 0.0)));
+        assert!(geofence.contains(&local(3.0, 3.0)));
+        assert!(geofence.contains(&local(7.0, 10.0)));
+    }
+
+    #[test]
+    fn distance_outside_polygon() {
+        let geofence = concave();
+
+        // to the nearest vertex
+        assert_eq!(geofence.distance_m(&local(13.0, 14.0)), 5.0);
+        // to the nearest edge
+        assert_eq!(geofence.distance_m(&local(-2.0, 5.0)), 2.0);
+        assert!(geofence.distance_m(&local(f64::NAN, 5.0)).is_nan());
+
+        // projected around the latitude of the location
+        let geofence = square();
+        let location = global(127.02, 37.005);
+        let expected = 0.01_f64.to_radians() * EARTH_RADIUS_M * 37.005_f64.to_radians().cos();
+        assert!((geofence.distance_m(&location) - expected).abs() < 1e-6);
+        assert!(geofence.contains(&global(127.005, 37.005)));
+    }
+
+    #[test]
+    fn distance_global_circle() {
+        let circle = geofence(
+            Coordinates::Global,
+            GeofenceShape::Circle {
+                center: [127.0, 37.0],
+                radius_m: 100.0,
+            },
+        );
+        assert!(circle.contains(&global(127.0, 37.0005)));
+        let expected = 0.001_f64.to_radians() * EARTH_RADIUS_M - 100.0;
+        assert!((circle.distance_m(&global(127.0, 37.001)) - expected).abs() < 1e-6);
+
+        // along the great circle, not across the projected plane
+        let point = geofence(
+            Coordinates::Global,
+            GeofenceShape::Circle {
+                center: [0.0, 0.0],
+                radius_m: 0.0,
+            },
+        );
+        let expected = PI * EARTH_RADIUS_M;
+        assert!((point.distance_m(&global(180.0, 0.0)) - expected).abs() < 1e-6);
+    }
+}
@@ -3,8 +3,20 @@ use std::{
     ops::{Add, Mul},
 };
 
+use chrono::{DateTime, Utc};
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+pub use self::geofence::{
+    Coordinates, Geofence, GeofenceEvent, GeofenceEventKind, GeofenceMembership, GeofenceShape,
+};
+
+mod geofence;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+
+/// Mean radius of the earth (WGS84), as meters.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct LocationData {
@@ -12,7 +24,118 @@ pub struct LocationData {
     pub location: Location,
 }
 
+/// An error returned by the servers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed (400)
+    BadRequest,
+    /// The caller is not authenticated (401)
+    Unauthorized,
+    /// The caller is not allowed to access the object (403)
+    Forbidden,
+    /// No such object (404)
+    NotFound,
+    /// The operation is not supported by the backend (501)
+    NotImplemented,
+    /// The backend (e.g. Prometheus) failed or is unreachable (502)
+    BadGateway,
+    /// The backend did not respond in time (504)
+    GatewayTimeout,
+    /// An unexpected error within the server (500)
+    Internal,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TimedLocationData {
+    pub data: DataRef,
+    pub location: TimedLocation,
+}
+
+/// Objects to receive the location updates of; every object is subscribed if empty.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Subscription {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objects: Vec<DataRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<DataRefFilter>,
+}
+
+/// Sent on the location streams when the subscriber has missed some updates,
+/// followed by the current locations of the subscribed objects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct StreamLagged {
+    /// The number of the missed updates
+    pub lagged: u64,
+}
+
+/// A page of the objects with their latest locations.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ObjectList {
+    pub items: Vec<TimedLocationData>,
+    /// Number of all matched objects, regardless of the pagination
+    pub total: usize,
+    /// Offset of the next page, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+}
+
+/// Finds the objects within `radius_m` from the center, sorted by the distance.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ProximityQuery {
+    pub center: ProximityCenter,
+    pub radius_m: f64,
+    /// Subtracts the errors of both locations from the distances, i.e. finds the objects
+    /// which may be within the radius
+    #[serde(default)]
+    pub with_error: bool,
+    #[serde(default)]
+    pub filter: DataRefFilter,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProximityCenter {
+    /// Measures the haversine distances from the point
+    Global(GlobalLocation),
+    /// Measures the euclidean distances from the point, within the same site
+    Local(LocalLocation),
+    /// Measures the distances from the latest location of the object, except itself
+    Object {
+        data: DataRef,
+        #[serde(default)]
+        metric: DistanceMetric,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceMetric {
+    /// The haversine distance of the global locations
+    #[default]
+    Global,
+    /// The euclidean distance of the local locations, within the same site
+    Local,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NearbyObject {
+    pub data: DataRef,
+    pub location: TimedLocation,
+    pub distance_m: f64,
+}
+
+#[derive(
+    Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
 pub struct DataRef {
     pub kind: String,
     pub name: String,
@@ -20,6 +143,30 @@ pub struct DataRef {
     pub namespace: Option<String>,
 }
 
+impl DataRef {
+    /// Returns the `(group, version, plural)` of the kubernetes resource,
+    /// e.g. `users.vine.ulagbulag.io/v1alpha1` or `pods/v1` for the core group.
+    pub fn resource(&self) -> Option<(&str, &str, &str)> {
+        let (resource, version) = self.kind.split_once('/')?;
+        let (plural, group) = resource.split_once('.').unwrap_or((resource, ""));
+        if plural.is_empty() || version.is_empty() {
+            return None;
+        }
+        Some((group, version, plural))
+    }
+}
+
+/// Regular expressions (fully anchored) on the fields of [`DataRef`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct DataRefFilter {
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Base {
     pub location: GlobalLocation,
@@ -30,7 +177,11 @@ impl Add<Location> for Base {
     type Output = Location;
 
     fn add(self, location: Location) -> Self::Output {
-        let Location { global, local } = location;
+        let Location {
+            global,
+            local,
+            motion,
+        } = location;
 
         let length =
             (global.latitude * global.latitude + global.longitude * global.longitude).sqrt();
@@ -50,6 +201,7 @@ impl Add<Location> for Base {
                 longitude: self.location.longitude + longitude,
             },
             local,
+            motion: motion.rotate(self.rotation),
         }
     }
 }
@@ -61,31 +213,73 @@ pub struct ObjectLocation {
     pub location: Location,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TimedLocation {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub location: Location,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Location {
     #[serde(flatten)]
     pub global: GlobalLocation,
     #[serde(flatten)]
     pub local: LocalLocation,
+    #[serde(default, flatten)]
+    pub motion: Motion,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct GlobalLocation {
+    #[serde(deserialize_with = "deserialize_f64_or_nan")]
     pub error_m: f64,
+    #[serde(deserialize_with = "deserialize_f64_or_nan")]
     pub latitude: f64,
+    #[serde(deserialize_with = "deserialize_f64_or_nan")]
     pub longitude: f64,
 }
 
+impl GlobalLocation {
+    /// Returns the (east, north) offset of `other` as meters,
+    /// using a local equirectangular projection.
+    pub fn offset_m(&self, other: &Self) -> (f64, f64) {
+        let north = EARTH_RADIUS_M * f64::consts::PI / 180.0;
+        let east = north * self.latitude.to_radians().cos();
+        (
+            (other.longitude - self.longitude) * east,
+            (other.latitude - self.latitude) * north,
+        )
+    }
+
+    /// Returns the great-circle distance to `other` as meters, using the haversine formula.
+    pub fn distance_m(&self, other: &Self) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct LocalLocation {
-    #[serde(rename = "local_x")]
+    #[serde(rename = "local_x", deserialize_with = "deserialize_f64_or_nan")]
     pub x: f64,
-    #[serde(rename = "local_y")]
+    #[serde(rename = "local_y", deserialize_with = "deserialize_f64_or_nan")]
     pub y: f64,
-    #[serde(rename = "local_error_m")]
+    #[serde(rename = "local_error_m", deserialize_with = "deserialize_f64_or_nan")]
     pub error_m: f64,
 }
 
+impl LocalLocation {
+    /// Returns the euclidean distance to `other`, assuming both are within the same site.
+    pub fn distance_m(&self, other: &Self) -> f64 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
+}
+
 impl Mul<LocationVectorScale> for LocalLocation {
     type Output = Location;
 
@@ -97,6 +291,52 @@ impl Mul<LocationVectorScale> for LocalLocation {
                 longitude: self.x * scale.longitude,
             },
             local: self,
+            motion: Motion::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Motion {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity_east_mps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity_north_mps: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_mps: Option<f64>,
+    /// Clockwise from the north, as degrees in `[0, 360)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading_deg: Option<f64>,
+}
+
+impl Motion {
+    pub fn from_velocity(east_mps: f64, north_mps: f64) -> Self {
+        Self {
+            velocity_east_mps: Some(east_mps),
+            velocity_north_mps: Some(north_mps),
+            speed_mps: Some(east_mps.hypot(north_mps)),
+            heading_deg: Some(east_mps.atan2(north_mps).to_degrees().rem_euclid(360.0)),
+        }
+    }
+
+    pub fn from_speed(speed_mps: f64, heading_deg: f64) -> Self {
+        let heading = heading_deg.to_radians();
+        Self {
+            velocity_east_mps: Some(speed_mps * heading.sin()),
+            velocity_north_mps: Some(speed_mps * heading.cos()),
+            speed_mps: Some(speed_mps),
+            heading_deg: Some(heading_deg.rem_euclid(360.0)),
+        }
+    }
+
+    /// Rotates the velocity counterclockwise by `rotation` radians.
+    pub fn rotate(self, rotation: f64) -> Self {
+        match (self.velocity_east_mps, self.velocity_north_mps) {
+            (Some(east), Some(north)) => {
+                let (sin, cos) = rotation.sin_cos();
+                Self::from_velocity(east * cos - north * sin, east * sin + north * cos)
+            }
+            _ => self,
         }
     }
 }
@@ -106,3 +346,11 @@ pub struct LocationVectorScale {
     pub latitude: f64,
     pub longitude: f64,
 }
+
+/// Reads `null` as NaN, as `serde_json` writes the non-finite numbers as `null`.
+fn deserialize_f64_or_nan<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<f64>::deserialize(deserializer).map(|value| value.unwrap_or(f64::NAN))
+}
@@ -0,0 +1,223 @@
+//! A minimal OpenAPI 3.0 document builder, describing the routes with the [`JsonSchema`] types.
+
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use crate::ErrorResponse;
+
+pub struct OpenApi {
+    generator: SchemaGenerator,
+    info: Value,
+    paths: Map<String, Value>,
+}
+
+impl OpenApi {
+    pub fn new(title: &str, version: &str) -> Self {
+        Self {
+            generator: SchemaSettings::openapi3().into_generator(),
+            info: json!({
+                "title": title,
+                "version": version,
+            }),
+            paths: Map::default(),
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.info["description"] = description.into();
+        self
+    }
+
+    pub fn route(
+        mut self,
+        method: &str,
+        path: &str,
+        f: impl FnOnce(Operation<'_>) -> Operation<'_>,
+    ) -> Self {
+        let operation = f(Operation {
+            generator: &mut self.generator,
+            operation: Map::default(),
+        })
+        .operation;
+
+        if let Value::Object(methods) = self
+            .paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::default()))
+        {
+            methods.insert(method.to_lowercase(), Value::Object(operation));
+        }
+        self
+    }
+
+    /// Describes the routes of the table.
+    pub fn routes<H>(self, routes: &[Route<H>]) -> Self {
+        routes.iter().fold(self, |openapi, route| {
+            openapi.route(route.method, route.path, route.document)
+        })
+    }
+
+    /// Adds the schema of the type, even if no routes refer to it.
+    pub fn with_schema<T>(mut self) -> Self
+    where
+        T: JsonSchema,
+    {
+        self.generator.subschema_for::<T>();
+        self
+    }
+
+    pub fn build(mut self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": self.info,
+            "paths": self.paths,
+            "components": {
+                "schemas": self.generator.take_definitions(),
+            },
+        })
+    }
+}
+
+/// An entry of a route table, from which a service both registers its handlers
+/// and describes them, not to let them drift apart.
+pub struct Route<H> {
+    /// The lowercase HTTP method, e.g. `get`
+    pub method: &'static str,
+    pub path: &'static str,
+    pub handler: H,
+    pub document: fn(Operation<'_>) -> Operation<'_>,
+}
+
+pub struct Operation<'a> {
+    generator: &'a mut SchemaGenerator,
+    operation: Map<String, Value>,
+}
+
+impl<'a> Operation<'a> {
+    pub fn summary(mut self, summary: &str) -> Self {
+        self.operation.insert("summary".into(), summary.into());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.operation
+            .insert("description".into(), description.into());
+        self
+    }
+
+    /// Adds the fields of the type as the query parameters.
+    pub fn query<T>(mut self) -> Self
+    where
+        T: JsonSchema,
+    {
+        let schema = to_value(self.generator.root_schema_for::<T>().schema);
+        let required = schema["required"].as_array().cloned().unwrap_or_default();
+
+        let parameters = self
+            .operation
+            .entry("parameters")
+            .or_insert_with(|| Value::Array(Vec::default()));
+        if let (Value::Array(parameters), Some(properties)) =
+            (parameters, schema["properties"].as_object())
+        {
+            for (name, schema) in properties {
+                let mut schema = schema.clone();
+                let description = schema
+                    .as_object_mut()
+                    .and_then(|schema| schema.remove("description"));
+
+                let mut parameter = json!({
+                    "name": name,
+                    "in": "query",
+                    "required": required.contains(&Value::String(name.clone())),
+                    "schema": schema,
+                });
+                if let Some(description) = description {
+                    parameter["description"] = description;
+                }
+                parameters.push(parameter);
+            }
+        }
+        self
+    }
+
+    pub fn body<T>(mut self) -> Self
+    where
+        T: JsonSchema,
+    {
+        let schema = to_value(self.generator.subschema_for::<T>());
+        self.operation.insert(
+            "requestBody".into(),
+            json!({
+                "required": true,
+                "content": {
+                    "application/json": {
+                        "schema": schema,
+                    },
+                },
+            }),
+        );
+        self
+    }
+
+    /// Returns the schema of the type, to be used in the custom contents.
+    pub fn schema<T>(&mut self) -> Value
+    where
+        T: JsonSchema,
+    {
+        to_value(self.generator.subschema_for::<T>())
+    }
+
+    /// Adds a JSON response.
+    pub fn response<T>(mut self, status: u16, description: &str) -> Self
+    where
+        T: JsonSchema,
+    {
+        let schema = self.schema::<T>();
+        self.response_with(status, description, &[("application/json", schema)])
+    }
+
+    pub fn response_with(
+        mut self,
+        status: u16,
+        description: &str,
+        content: &[(&str, Value)],
+    ) -> Self {
+        let mut response = json!({
+            "description": description,
+        });
+        if !content.is_empty() {
+            response["content"] = content
+                .iter()
+                .map(|(content_type, schema)| {
+                    (content_type.to_string(), json!({ "schema": schema }))
+                })
+                .collect::<Map<_, _>>()
+                .into();
+        }
+
+        if let Value::Object(responses) = self
+            .operation
+            .entry("responses")
+            .or_insert_with(|| Value::Object(Map::default()))
+        {
+            responses.insert(status.to_string(), response);
+        }
+        self
+    }
+
+    /// Adds the [`ErrorResponse`]s with the given status codes.
+    pub fn errors(mut self, statuses: &[u16]) -> Self {
+        for &status in statuses {
+            self = self.response::<ErrorResponse>(status, "An error");
+        }
+        self
+    }
+}
+
+fn to_value(schema: impl ::serde::Serialize) -> Value {
+    ::serde_json::to_value(schema).expect("schemas should be serializable")
+}
@@ -1,8 +1,17 @@
-use anyhow::Result;
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
-use footprint_api::{DataRef, GlobalLocation, LocalLocation, Location};
+use footprint_api::{
+    DataRef, DataRefFilter, ErrorResponse, Geofence, GeofenceMembership, GlobalLocation,
+    LocalLocation, Location, LocationData, Motion, NearbyObject, ObjectList, ObjectLocation,
+    ProximityQuery, StreamLagged, Subscription, TimedLocation, TimedLocationData,
+};
 use footprint_client::Client;
 use reqwest::Url;
+use schemars::gen::SchemaSettings;
+use serde_json::json;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -20,7 +29,10 @@ impl Args {
 #[derive(Subcommand)]
 enum Commands {
     Get(CommandGet),
+    History(CommandHistory),
+    List(CommandList),
     Query(CommandQuery),
+    Schema(CommandSchema),
     Update(CommandUpdate),
 }
 
@@ -28,7 +40,10 @@ impl Commands {
     async fn run(self) -> Result<()> {
         match self {
             Self::Get(command) => command.run().await,
+            Self::History(command) => command.run().await,
+            Self::List(command) => command.run().await,
             Self::Query(command) => command.run().await,
+            Self::Schema(command) => command.run(),
             Self::Update(command) => command.run().await,
         }
     }
@@ -66,7 +81,7 @@ impl CommandGet {
         };
 
         // Push metrics
-        let client = Client::new(self.client.url)?;
+        let client = self.client.build()?;
         let response = if self.raw {
             client.get_raw(&data).await
         } else {
@@ -81,6 +96,100 @@ impl CommandGet {
     }
 }
 
+/// Get a trajectory of a resource from the storage.
+#[derive(Parser)]
+struct CommandHistory {
+    #[command(flatten)]
+    client: ArgsClient,
+
+    /// Search by kind
+    #[arg(long, value_name = "KIND")]
+    kind: String,
+
+    /// Search by name
+    #[arg(long, value_name = "NAME")]
+    name: String,
+
+    /// Search by namespace
+    #[arg(long, value_name = "NAMESPACE")]
+    namespace: Option<String>,
+
+    /// Start time, as RFC 3339
+    #[arg(long, value_name = "START")]
+    start: DateTime<Utc>,
+
+    /// End time, as RFC 3339 (default: now)
+    #[arg(long, value_name = "END")]
+    end: Option<DateTime<Utc>>,
+
+    /// Resolution step as seconds
+    #[arg(long, value_name = "STEP", default_value_t = 15.0)]
+    step: f64,
+}
+
+impl CommandHistory {
+    async fn run(self) -> Result<()> {
+        let data = DataRef {
+            kind: self.kind,
+            name: self.name,
+            namespace: self.namespace,
+        };
+        let end = self.end.unwrap_or_else(Utc::now);
+        let step = Duration::try_from_secs_f64(self.step)
+            .map_err(|error| anyhow!("invalid step: {}: {error}", self.step))?;
+        if step.is_zero() {
+            bail!("the step should be positive");
+        }
+
+        let client = self.client.build()?;
+        client
+            .history(&data, self.start, end, step)
+            .await
+            .map_err(Into::into)
+            .map(|values| values.into_iter().for_each(|value| println!("{value:?}")))
+    }
+}
+
+/// List all resources from the storage.
+#[derive(Parser)]
+struct CommandList {
+    #[command(flatten)]
+    client: ArgsClient,
+
+    /// Search by kind, as a regular expression
+    #[arg(long, value_name = "KIND")]
+    kind: Option<String>,
+
+    /// Search by name, as a regular expression
+    #[arg(long, value_name = "NAME")]
+    name: Option<String>,
+
+    /// Search by namespace, as a regular expression
+    #[arg(long, value_name = "NAMESPACE")]
+    namespace: Option<String>,
+}
+
+impl CommandList {
+    async fn run(self) -> Result<()> {
+        let filter = DataRefFilter {
+            kind: self.kind,
+            name: self.name,
+            namespace: self.namespace,
+        };
+
+        let client = self.client.build()?;
+        client
+            .list(&filter)
+            .await
+            .map_err(Into::into)
+            .map(|values| {
+                values
+                    .into_iter()
+                    .for_each(|(data, location)| println!("{data:?}: {location:?}"))
+            })
+    }
+}
+
 /// Create a resource from a file or from stdin.
 #[derive(Parser)]
 struct CommandQuery {
@@ -95,7 +204,7 @@ struct CommandQuery {
 impl CommandQuery {
     async fn run(self) -> Result<()> {
         // Push metrics
-        let client = Client::new(self.client.url)?;
+        let client = self.client.build()?;
         client
             .get_raw_vec_all_by_query(&self.query)
             .await
@@ -104,12 +213,71 @@ impl CommandQuery {
     }
 }
 
+/// Print the JSON Schemas of the API types.
+#[derive(Parser)]
+struct CommandSchema {
+    /// Print the schema of the given type only, e.g. "Location"
+    #[arg(value_name = "TYPE")]
+    name: Option<String>,
+}
+
+impl CommandSchema {
+    fn run(self) -> Result<()> {
+        let mut generator = SchemaSettings::draft07().into_generator();
+        generator.subschema_for::<DataRef>();
+        generator.subschema_for::<DataRefFilter>();
+        generator.subschema_for::<ErrorResponse>();
+        generator.subschema_for::<Geofence>();
+        generator.subschema_for::<GeofenceMembership>();
+        generator.subschema_for::<Location>();
+        generator.subschema_for::<LocationData>();
+        generator.subschema_for::<NearbyObject>();
+        generator.subschema_for::<ObjectList>();
+        generator.subschema_for::<ObjectLocation>();
+        generator.subschema_for::<ProximityQuery>();
+        generator.subschema_for::<StreamLagged>();
+        generator.subschema_for::<Subscription>();
+        generator.subschema_for::<TimedLocation>();
+        generator.subschema_for::<TimedLocationData>();
+
+        let definitions = generator.take_definitions();
+        let mut schema = json!({
+            "$schema": generator.settings().meta_schema,
+            "definitions": definitions,
+        });
+        if let Some(name) = self.name {
+            if !schema["definitions"]
+                .as_object()
+                .is_some_and(|definitions| definitions.contains_key(&name))
+            {
+                bail!("no such type: {name}");
+            }
+            schema["$ref"] = format!("#/definitions/{name}").into();
+        }
+
+        println!("{}", ::serde_json::to_string_pretty(&schema)?);
+        Ok(())
+    }
+}
+
 /// Create a resource from a file or from stdin.
 #[derive(Parser)]
 struct CommandUpdate {
     #[command(flatten)]
     client: ArgsClient,
 
+    /// Set a kind
+    #[arg(long, value_name = "KIND")]
+    kind: String,
+
+    /// Set a name
+    #[arg(long, value_name = "NAME")]
+    name: String,
+
+    /// Set a namespace
+    #[arg(long, value_name = "NAMESPACE")]
+    namespace: Option<String>,
+
     /// Set an error as meter
     #[arg(long, value_name = "ERROR_M")]
     error_m: f64,
@@ -121,10 +289,23 @@ struct CommandUpdate {
     /// Set a longitude
     #[arg(long, value_name = "LONGITUDE")]
     longitude: f64,
+
+    /// Set a speed as meter per second
+    #[arg(long, value_name = "SPEED_MPS", requires = "heading_deg")]
+    speed_mps: Option<f64>,
+
+    /// Set a heading as degree, clockwise from the north
+    #[arg(long, value_name = "HEADING_DEG", requires = "speed_mps")]
+    heading_deg: Option<f64>,
 }
 
 impl CommandUpdate {
     async fn run(self) -> Result<()> {
+        let data = DataRef {
+            kind: self.kind,
+            name: self.name,
+            namespace: self.namespace,
+        };
         let location = Location {
             global: GlobalLocation {
                 error_m: self.error_m,
@@ -132,11 +313,18 @@ impl CommandUpdate {
                 longitude: self.longitude,
             },
             local: LocalLocation::default(),
+            motion: match (self.speed_mps, self.heading_deg) {
+                (Some(speed_mps), Some(heading_deg)) => Motion::from_speed(speed_mps, heading_deg),
+                _ => Motion::default(),
+            },
         };
 
         // Push metrics
-        let writer = Client::new(self.client.url)?;
-        writer.put(&location).await.map_err(Into::into)
+        let writer = self.client.build()?;
+        writer
+            .put(&LocationData { data, location })
+            .await
+            .map_err(Into::into)
     }
 }
 
@@ -146,6 +334,45 @@ struct ArgsClient {
     /// Prometheus URL
     #[arg(long, env = "FOOTPRINT_URL", value_name = "URL")]
     url: Url,
+
+    /// Bearer token
+    #[arg(
+        long,
+        env = "FOOTPRINT_TOKEN",
+        value_name = "TOKEN",
+        hide_env_values = true
+    )]
+    token: Option<String>,
+
+    /// Path of a file containing a bearer token
+    #[arg(long, env = "FOOTPRINT_TOKEN_FILE", value_name = "PATH")]
+    token_file: Option<PathBuf>,
+
+    /// Path of an additional CA certificate, as PEM
+    #[arg(long, env = "FOOTPRINT_CA_FILE", value_name = "PATH")]
+    ca_file: Option<PathBuf>,
+
+    /// Request timeout as seconds
+    #[arg(long, env = "FOOTPRINT_TIMEOUT_SEC", value_name = "SECONDS")]
+    timeout_sec: Option<f64>,
+}
+
+impl ArgsClient {
+    fn build(self) -> Result<Client> {
+        let mut builder = Client::builder(self.url);
+        if let Some(token) = self.token {
+            builder = builder.bearer_token(token);
+        } else if let Some(path) = self.token_file {
+            builder = builder.bearer_token_file(path);
+        }
+        if let Some(path) = self.ca_file {
+            builder = builder.ca_certificate_file(path)?;
+        }
+        if let Some(timeout) = self.timeout_sec {
+            builder = builder.timeout(Duration::try_from_secs_f64(timeout)?);
+        }
+        builder.build().map_err(Into::into)
+    }
 }
 
 #[tokio::main]
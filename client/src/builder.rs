@@ -0,0 +1,264 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Certificate, Identity, RequestBuilder, Url,
+};
+
+use crate::{Client, Error, Result};
+
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    url: Url,
+    auth: Option<Auth>,
+    ca_certificates: Vec<Vec<u8>>,
+    connect_timeout: Option<Duration>,
+    headers: HeaderMap,
+    identity: Option<Vec<u8>>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+}
+
+impl ClientBuilder {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            auth: None,
+            ca_certificates: Vec::default(),
+            connect_timeout: Some(Duration::from_secs(5)),
+            headers: HeaderMap::default(),
+            identity: None,
+            retry: RetryPolicy::default(),
+            timeout: Some(Duration::from_secs(30)),
+        }
+    }
+
+    /// Loads the configuration from the `FOOTPRINT_*` environment variables.
+    pub fn try_from_env() -> Result<Self> {
+        let url = env::var("FOOTPRINT_URL")
+            .unwrap_or_else(|_| "http://prometheus-operated.vine.svc:9090".into())
+            .parse()?;
+        let mut builder = Self::new(url);
+
+        if let Some(timeout) = env_secs("FOOTPRINT_CONNECT_TIMEOUT_SEC")? {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = env_secs("FOOTPRINT_TIMEOUT_SEC")? {
+            builder = builder.timeout(timeout);
+        }
+        if let Ok(max_retries) = env::var("FOOTPRINT_MAX_RETRIES") {
+            builder.retry.max_retries = max_retries
+                .parse()
+                .map_err(|error| Error::Config(format!("FOOTPRINT_MAX_RETRIES: {error}")))?;
+        }
+
+        if let Ok(token) = env::var("FOOTPRINT_TOKEN") {
+            builder = builder.bearer_token(token);
+        } else if let Ok(path) = env::var("FOOTPRINT_TOKEN_FILE") {
+            builder = builder.bearer_token_file(path);
+        } else if let Ok(username) = env::var("FOOTPRINT_USERNAME") {
+            builder = builder.basic_auth(username, env::var("FOOTPRINT_PASSWORD").ok());
+        }
+
+        // e.g. FOOTPRINT_HEADER_X_SCOPE_ORGID=tenant => X-Scope-OrgID: tenant
+        for (key, value) in env::vars() {
+            if let Some(name) = key.strip_prefix("FOOTPRINT_HEADER_") {
+                builder = builder.header(&name.replace('_', "-"), &value)?;
+            }
+        }
+
+        if let Ok(path) = env::var("FOOTPRINT_CA_FILE") {
+            builder = builder.ca_certificate_file(path)?;
+        }
+        match (
+            env::var("FOOTPRINT_CERT_FILE").ok(),
+            env::var("FOOTPRINT_KEY_FILE").ok(),
+        ) {
+            (Some(cert), Some(key)) => builder = builder.identity_files(cert, key)?,
+            (None, None) => {}
+            _ => {
+                return Err(Error::Config(
+                    "both FOOTPRINT_CERT_FILE and FOOTPRINT_KEY_FILE should be given".into(),
+                ))
+            }
+        }
+        Ok(builder)
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timeout of a single request, including reading the response.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.auth = Some(Auth::Bearer(token.into()));
+        self
+    }
+
+    /// Reads the bearer token from the file on every request, so that rotated tokens are used.
+    pub fn bearer_token_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.auth = Some(Auth::BearerFile(path.into()));
+        self
+    }
+
+    pub fn basic_auth(mut self, username: impl Into<String>, password: Option<String>) -> Self {
+        self.auth = Some(Auth::Basic {
+            username: username.into(),
+            password,
+        });
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Result<Self> {
+        let name = HeaderName::try_from(name)
+            .map_err(|error| Error::Config(format!("invalid header name: {name}: {error}")))?;
+        let value = HeaderValue::try_from(value)
+            .map_err(|error| Error::Config(format!("invalid header value: {name}: {error}")))?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    /// Trusts an additional root certificate, as PEM.
+    pub fn ca_certificate(mut self, pem: Vec<u8>) -> Self {
+        self.ca_certificates.push(pem);
+        self
+    }
+
+    pub fn ca_certificate_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        read_file(path.as_ref()).map(|pem| self.ca_certificate(pem))
+    }
+
+    /// Uses a client certificate for mutual TLS, as PEM containing both the certificate chain
+    /// and the private key.
+    pub fn identity(mut self, pem: Vec<u8>) -> Self {
+        self.identity = Some(pem);
+        self
+    }
+
+    pub fn identity_files(self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self> {
+        let mut pem = read_file(cert.as_ref())?;
+        pem.push(b'\n');
+        pem.extend(read_file(key.as_ref())?);
+        Ok(self.identity(pem))
+    }
+
+    pub fn build(self) -> Result<Client> {
+        let mut builder = ::reqwest::ClientBuilder::new()
+            .default_headers(self.headers)
+            .use_rustls_tls();
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        for pem in &self.ca_certificates {
+            builder = builder.add_root_certificate(Certificate::from_pem(pem)?);
+        }
+        if let Some(pem) = &self.identity {
+            builder = builder.identity(Identity::from_pem(pem)?);
+        }
+
+        Ok(Client {
+            auth: self.auth,
+            inner: builder.build()?,
+            retry: self.retry,
+            url: self.url,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    BearerFile(PathBuf),
+}
+
+impl Auth {
+    pub(crate) fn apply(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        match self {
+            Self::Basic { username, password } => {
+                Ok(request.basic_auth(username, password.as_ref()))
+            }
+            Self::Bearer(token) => Ok(request.bearer_auth(token)),
+            Self::BearerFile(path) => read_file(path)
+                .map(|token| request.bearer_auth(String::from_utf8_lossy(&token).trim())),
+        }
+    }
+}
+
+/// Retries idempotent requests on network errors and on `429` or `5xx` responses,
+/// with an exponential backoff.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub const fn disabled() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+fn env_secs(key: &str) -> Result<Option<Duration>> {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|error| Error::Config(format!("{key}: {error}")))
+            .and_then(|value| {
+                Duration::try_from_secs_f64(value)
+                    .map_err(|error| Error::Config(format!("{key}: {error}")))
+            })
+            .map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
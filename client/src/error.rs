@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+pub type Result<T, E = Error> = ::std::result::Result<T, E>;
+
+#[derive(Debug, ::thiserror::Error)]
+pub enum Error {
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: ::std::io::Error,
+    },
+    #[error("invalid url: {0}")]
+    Url(#[from] ::url::ParseError),
+    #[error("failed to communicate with the server: {0}")]
+    Network(#[from] ::reqwest::Error),
+    #[error("unexpected HTTP status {status}: {body}")]
+    Http { status: StatusCode, body: String },
+    #[error("prometheus error ({error_type}): {error}")]
+    Prometheus {
+        error_type: PrometheusErrorType,
+        error: String,
+    },
+    #[error("failed to decode the response: {0}")]
+    Decode(#[from] ::serde_json::Error),
+    #[error("invalid filter: {0}")]
+    InvalidFilter(#[from] ::regex::Error),
+    #[error("unexpected result type: {0}")]
+    UnexpectedResultType(&'static str),
+    #[error("too many points of the trajectory: {points} > {max}", max = crate::store::MAX_HISTORY_POINTS)]
+    TooManyPoints { points: u128 },
+    #[error("the step should be positive")]
+    ZeroStep,
+    #[error("not found")]
+    NotFound,
+    #[error("unsupported operation: {0}")]
+    Unsupported(&'static str),
+}
+
+/// Error types of the Prometheus HTTP API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrometheusErrorType {
+    BadData,
+    Canceled,
+    Execution,
+    Internal,
+    NotAcceptable,
+    NotFound,
+    Timeout,
+    Unavailable,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ::std::fmt::Display for PrometheusErrorType {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        f.write_str(match self {
+            Self::BadData => "bad_data",
+            Self::Canceled => "canceled",
+            Self::Execution => "execution",
+            Self::Internal => "internal",
+            Self::NotAcceptable => "not_acceptable",
+            Self::NotFound => "not_found",
+            Self::Timeout => "timeout",
+            Self::Unavailable => "unavailable",
+            Self::Unknown => "unknown",
+        })
+    }
+}
@@ -1,98 +1,365 @@
-use std::{env, str::FromStr};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    str::FromStr,
+    time::Duration,
+};
 
-use anyhow::{bail, Result};
-use chrono::Utc;
-use footprint_api::{DataRef, GlobalLocation, LocalLocation, Location};
+use chrono::{DateTime, Utc};
+use footprint_api::{
+    DataRef, DataRefFilter, GlobalLocation, LocalLocation, Location, LocationData, Motion,
+    ObjectList, TimedLocation, TimedLocationData,
+};
 use footprint_provider_api::consts;
 use futures::try_join;
-use reqwest::Url;
+use reqwest::{RequestBuilder, Response, StatusCode, Url};
 use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use tokio::time::sleep;
+
+use self::store::check_history_points;
+
+pub use self::{
+    builder::{ClientBuilder, RetryPolicy},
+    error::{Error, PrometheusErrorType, Result},
+    selector::{LabelMatcher, MatchOp, Selector},
+    store::{DataRefMatcher, LocationStore, MemoryStore, SegmentStore, SegmentStoreConfig},
+};
+
+mod builder;
+mod error;
+pub mod selector;
+pub mod store;
 
 pub struct Client {
+    auth: Option<self::builder::Auth>,
     inner: ::reqwest::Client,
+    retry: RetryPolicy,
     url: Url,
 }
 
 impl Client {
     pub fn new(url: Url) -> Result<Self> {
-        ::reqwest::ClientBuilder::new()
-            .build()
-            .map(|inner| Self { inner, url })
-            .map_err(Into::into)
+        Self::builder(url).build()
     }
 
-    pub fn try_default() -> Result<Self> {
-        let url = env::var("FOOTPRINT_URL")
-            .unwrap_or_else(|_| "http://prometheus-operated.vine.svc:9090".into())
-            .parse()?;
+    pub fn builder(url: Url) -> ClientBuilder {
+        ClientBuilder::new(url)
+    }
 
-        Self::new(url)
+    pub fn try_default() -> Result<Self> {
+        ClientBuilder::try_from_env()?.build()
     }
 
     pub async fn get(&self, query: &DataRef) -> Result<Option<Location>> {
         let url = self.url.clone();
 
-        let response = self.inner.get(url).query(query).send().await?;
-        let status = response.status();
+        let response = self
+            .send_idempotent(self.inner.get(url).query(query))
+            .await?;
+        match response.status() {
+            status if status.is_success() => decode(response).await,
+            StatusCode::NOT_FOUND => Err(Error::NotFound),
+            status => Err(Error::Http {
+                status,
+                body: response.text().await.unwrap_or_default(),
+            }),
+        }
+    }
 
-        if status.is_success() {
-            response.json().await.map_err(Into::into)
-        } else {
-            let reason = status.canonical_reason().unwrap_or_default();
-            bail!("{reason}")
+    pub async fn get_raw(&self, data: &DataRef) -> Result<Option<Location>> {
+        let selector = Selector::default().with_data(data);
+
+        self.get_raw_all_by_selector(&selector)
+            .await
+            .map(|locations| {
+                locations
+                    .into_values()
+                    .next()
+                    .map(|location| location.location)
+            })
+    }
+
+    /// Returns the objects of the kind with their latest locations from the gateway,
+    /// as exposed to the caller by its policies.
+    pub async fn list_objects(
+        &self,
+        kind: &str,
+        namespace: Option<&str>,
+    ) -> Result<Vec<TimedLocationData>> {
+        let mut url = self.url.clone();
+        url.set_path(&format!(
+            "{base}/objects",
+            base = url.path().trim_end_matches('/'),
+        ));
+
+        let mut items = Vec::default();
+        let mut offset = 0;
+        loop {
+            let mut query = vec![("kind", kind.to_string()), ("offset", offset.to_string())];
+            if let Some(namespace) = namespace {
+                query.push(("namespace", namespace.into()));
+            }
+
+            let response = self
+                .send_idempotent(self.inner.get(url.clone()).query(&query))
+                .await?;
+            let list: ObjectList = match response.status() {
+                status if status.is_success() => decode(response).await?,
+                status => {
+                    return Err(Error::Http {
+                        status,
+                        body: response.text().await.unwrap_or_default(),
+                    })
+                }
+            };
+
+            items.extend(list.items);
+            match list.next_offset {
+                Some(next_offset) => offset = next_offset,
+                None => return Ok(items),
+            }
         }
     }
 
-    pub async fn get_raw(
+    /// Returns the latest locations of all objects matching the filter.
+    pub async fn list(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, Location>> {
+        self.list_timed(filter).await.map(|locations| {
+            locations
+                .into_iter()
+                .map(|(data, location)| (data, location.location))
+                .collect()
+        })
+    }
+
+    /// Returns the latest locations of all objects matching the filter, with the timestamps
+    /// when they were observed.
+    pub async fn list_timed(
         &self,
-        DataRef {
-            kind,
-            name,
-            namespace,
-        }: &DataRef,
-    ) -> Result<Option<Location>> {
-        let labels = format!(
-            "{label_kind}={kind:?},{label_name}={name:?},{label_namespace}={namespace:?}",
-            label_kind = consts::LABEL_KIND,
-            label_name = consts::LABEL_NAME,
-            label_namespace = consts::LABEL_NAMESPACE,
-            namespace = namespace.as_deref().unwrap_or_default(),
-        );
-
-        match try_join!(
-            {
-                let query = format!("{metric}{{{labels}}}", metric = consts::METRIC_ERROR_M,);
-                self.get_raw_one_by_query(query)
-            },
-            {
-                let query = format!("{metric}{{{labels}}}", metric = consts::METRIC_LATITUDE,);
-                self.get_raw_one_by_query(query)
-            },
-            {
-                let query = format!("{metric}{{{labels}}}", metric = consts::METRIC_LONGITUDE,);
-                self.get_raw_one_by_query(query)
-            },
-        )? {
-            (Some(error_m), Some(latitude), Some(longitude)) => Ok(Some(Location {
-                global: GlobalLocation {
-                    error_m,
-                    latitude,
-                    longitude,
-                },
-                local: LocalLocation::default(),
-            })),
-            _ => Ok(None),
+        filter: &DataRefFilter,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        self.list_timed_at(filter, Utc::now()).await
+    }
+
+    /// Returns the latest locations as of `time` of all objects matching the filter,
+    /// with the timestamps when they were observed.
+    pub async fn list_timed_at(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let selector = Selector::default().with_filter(filter);
+
+        self.get_raw_all_by_selector_at(&selector, time).await
+    }
+
+    async fn get_raw_all_by_selector(
+        &self,
+        selector: &Selector,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        self.get_raw_all_by_selector_at(selector, Utc::now()).await
+    }
+
+    async fn get_raw_all_by_selector_at(
+        &self,
+        selector: &Selector,
+        time: DateTime<Utc>,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let query = |metric| {
+            let query = selector.clone().with_metric(metric).to_string();
+            self.get_raw_vec_all_by_query_at(query, time)
+        };
+        let query_timestamp = || {
+            let selector = selector.clone().with_metric(consts::METRIC_ERROR_M);
+            self.get_raw_vec_all_by_query_at(format!("timestamp({selector})"), time)
+        };
+
+        let (
+            timestamp,
+            error_m,
+            latitude,
+            longitude,
+            velocity_east_mps,
+            velocity_north_mps,
+            speed_mps,
+            heading_deg,
+        ) = try_join!(
+            query_timestamp(),
+            query(consts::METRIC_ERROR_M),
+            query(consts::METRIC_LATITUDE),
+            query(consts::METRIC_LONGITUDE),
+            query(consts::METRIC_VELOCITY_EAST_MPS),
+            query(consts::METRIC_VELOCITY_NORTH_MPS),
+            query(consts::METRIC_SPEED_MPS),
+            query(consts::METRIC_HEADING_DEG),
+        )?;
+
+        // the series of an object are told apart by the other labels,
+        // e.g. of the provider replicas or restarts
+        type Series = (DataRef, BTreeMap<String, String>);
+
+        fn collect(series: Vec<QueryData<f64>>) -> BTreeMap<Series, f64> {
+            series
+                .into_iter()
+                .filter_map(|data| match data.value.get(1).copied() {
+                    Some(value) if !value.is_nan() => Some((data.metric.into_series(), value)),
+                    _ => None,
+                })
+                .collect()
         }
+
+        let mut timestamp = collect(timestamp);
+        let error_m = collect(error_m);
+        let mut latitude = collect(latitude);
+        let mut longitude = collect(longitude);
+        let mut velocity_east_mps = collect(velocity_east_mps);
+        let mut velocity_north_mps = collect(velocity_north_mps);
+        let mut speed_mps = collect(speed_mps);
+        let mut heading_deg = collect(heading_deg);
+
+        Ok(
+            error_m
+                .into_iter()
+                .filter_map(|(series, error_m)| {
+                    let data = &series;
+                    let location = Location {
+                        global: GlobalLocation {
+                            error_m,
+                            latitude: latitude.remove(data)?,
+                            longitude: longitude.remove(data)?,
+                        },
+                        local: LocalLocation::default(),
+                        motion: Motion {
+                            velocity_east_mps: velocity_east_mps.remove(data),
+                            velocity_north_mps: velocity_north_mps.remove(data),
+                            speed_mps: speed_mps.remove(data),
+                            heading_deg: heading_deg.remove(data),
+                        },
+                    };
+                    let timestamp = timestamp.remove(data)?;
+                    let location = TimedLocation {
+                        timestamp: DateTime::from_timestamp_millis(
+                            (timestamp * 1000.0).round() as i64
+                        )?,
+                        location,
+                    };
+                    Some((series.0, location))
+                })
+                // take the newest series of each object
+                .fold(BTreeMap::default(), |mut locations, (data, location)| {
+                    match locations.entry(data) {
+                        Entry::Vacant(entry) => {
+                            entry.insert(location);
+                        }
+                        Entry::Occupied(mut entry) => {
+                            if entry.get().timestamp < location.timestamp {
+                                entry.insert(location);
+                            }
+                        }
+                    }
+                    locations
+                }),
+        )
+    }
+
+    pub async fn history(
+        &self,
+        data: &DataRef,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<TimedLocation>> {
+        check_history_points(start, end, step)?;
+
+        let selector = Selector::default().with_data(data);
+
+        let query = |metric| {
+            let query = selector.clone().with_metric(metric).to_string();
+            self.get_raw_range_by_query(query, start, end, step)
+        };
+
+        let (
+            error_m,
+            latitude,
+            longitude,
+            velocity_east_mps,
+            velocity_north_mps,
+            speed_mps,
+            heading_deg,
+        ) = try_join!(
+            query(consts::METRIC_ERROR_M),
+            query(consts::METRIC_LATITUDE),
+            query(consts::METRIC_LONGITUDE),
+            query(consts::METRIC_VELOCITY_EAST_MPS),
+            query(consts::METRIC_VELOCITY_NORTH_MPS),
+            query(consts::METRIC_SPEED_MPS),
+            query(consts::METRIC_HEADING_DEG),
+        )?;
+
+        // zip the series by their timestamps
+        let mut samples: BTreeMap<i64, [Option<f64>; 7]> = BTreeMap::default();
+        for (index, series) in [
+            error_m,
+            latitude,
+            longitude,
+            velocity_east_mps,
+            velocity_north_mps,
+            speed_mps,
+            heading_deg,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            for (timestamp, value) in series {
+                if !value.is_nan() {
+                    samples.entry(timestamp).or_default()[index] = Some(value);
+                }
+            }
+        }
+
+        Ok(samples
+            .into_iter()
+            .filter_map(|(timestamp, values)| match values {
+                [Some(error_m), Some(latitude), Some(longitude), velocity_east_mps, velocity_north_mps, speed_mps, heading_deg] => {
+                    Some(TimedLocation {
+                        timestamp: DateTime::from_timestamp_millis(timestamp)?,
+                        location: Location {
+                            global: GlobalLocation {
+                                error_m,
+                                latitude,
+                                longitude,
+                            },
+                            local: LocalLocation::default(),
+                            motion: Motion {
+                                velocity_east_mps,
+                                velocity_north_mps,
+                                speed_mps,
+                                heading_deg,
+                            },
+                        },
+                    })
+                }
+                _ => None,
+            })
+            .collect())
     }
 
     pub async fn get_raw_vec_all_by_query(
         &self,
         query: impl AsRef<str>,
     ) -> Result<Vec<QueryData<f64>>> {
-        match self.get_by_query_with(query).await? {
-            QueryResponse::Success { data } => match data {
-                QueryResult::Vector(data) => Ok(data),
-            },
+        self.get_raw_vec_all_by_query_at(query, Utc::now()).await
+    }
+
+    pub async fn get_raw_vec_all_by_query_at(
+        &self,
+        query: impl AsRef<str>,
+        time: DateTime<Utc>,
+    ) -> Result<Vec<QueryData<f64>>> {
+        match self
+            .get_by_query_at_with::<QueryResult>(query, time)
+            .await?
+        {
+            QueryResult::Vector(data) => Ok(data),
+            data => Err(Error::UnexpectedResultType(data.result_type())),
         }
     }
 
@@ -102,59 +369,223 @@ impl Client {
             .map(|mut values| values.pop().and_then(|data| data.value.get(1).copied()))
     }
 
-    async fn get_by_query_with<T>(&self, query: impl AsRef<str>) -> Result<T>
+    pub async fn get_raw_matrix_all_by_query(
+        &self,
+        query: impl AsRef<str>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<QueryRangeData<f64>>> {
+        match self
+            .get_by_query_range_with::<QueryResult>(query, start, end, step)
+            .await?
+        {
+            QueryResult::Matrix(data) => Ok(data),
+            data => Err(Error::UnexpectedResultType(data.result_type())),
+        }
+    }
+
+    /// Returns the samples of the first series as `(timestamp in milliseconds, value)`.
+    pub async fn get_raw_range_by_query(
+        &self,
+        query: impl AsRef<str>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<(i64, f64)>> {
+        self.get_raw_matrix_all_by_query(query, start, end, step)
+            .await
+            .map(|mut values| {
+                values
+                    .pop()
+                    .map(|data| {
+                        data.values
+                            .into_iter()
+                            .filter_map(|value| match value.as_slice() {
+                                [timestamp, value] => {
+                                    Some(((timestamp * 1000.0).round() as i64, *value))
+                                }
+                                _ => None,
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            })
+    }
+
+    async fn get_by_query_at_with<T>(
+        &self,
+        query: impl AsRef<str>,
+        time: DateTime<Utc>,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let time = (time.timestamp_millis() as f64 / 1000.0).to_string();
+        self.get_by_path_with("query", &[("query", query.as_ref()), ("time", &time)])
+            .await
+    }
+
+    async fn get_by_query_range_with<T>(
+        &self,
+        query: impl AsRef<str>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let start = (start.timestamp_millis() as f64 / 1000.0).to_string();
+        let end = (end.timestamp_millis() as f64 / 1000.0).to_string();
+        let step = step.as_secs_f64().to_string();
+        self.get_by_path_with(
+            "query_range",
+            &[
+                ("query", query.as_ref()),
+                ("start", &start),
+                ("end", &end),
+                ("step", &step),
+            ],
+        )
+        .await
+    }
+
+    async fn get_by_path_with<T>(&self, path: &str, params: &[(&str, &str)]) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        let time = Utc::now().timestamp();
         let url = {
             let mut url = self.url.clone();
-            url.set_path(&format!("{path}/api/v1/query", path = url.path()));
-            url.set_query(Some(&format!(
-                "query={query}&time={time}",
-                query = query.as_ref(),
-            )));
+            url.set_path(&format!("{base}/api/v1/{path}", base = url.path()));
+            url.query_pairs_mut().clear().extend_pairs(params);
             url
         };
 
-        let response = self.inner.get(url).send().await?;
+        let response = self.send_idempotent(self.inner.get(url)).await?;
         let status = response.status();
+        let body = response.bytes().await?;
 
-        if status.is_success() {
-            Ok(response.json().await?)
-        } else {
-            let reason = status.canonical_reason().unwrap_or_default();
-            bail!("{reason}")
+        // Prometheus reports its errors as JSON, even with non-2xx status codes
+        match ::serde_json::from_slice(&body) {
+            Ok(QueryResponse::Success { data, warnings: _ }) => Ok(data),
+            Ok(QueryResponse::Error {
+                error_type,
+                error,
+                warnings: _,
+            }) => Err(Error::Prometheus { error_type, error }),
+            Err(error) if status.is_success() => Err(error.into()),
+            Err(_) => Err(Error::Http {
+                status,
+                body: String::from_utf8_lossy(&body).into(),
+            }),
         }
     }
 
-    pub async fn put(&self, location: &Location) -> Result<()> {
+    pub async fn put(&self, data: &LocationData) -> Result<()> {
         let url = self.url.clone();
 
-        let response = self.inner.put(url).json(location).send().await?;
+        let response = self
+            .authorize(self.inner.put(url).json(data))?
+            .send()
+            .await?;
         let status = response.status();
 
         if status.is_success() {
             Ok(())
         } else {
-            let reason = status.canonical_reason().unwrap_or_default();
-            bail!("{reason}")
+            Err(Error::Http {
+                status,
+                body: response.text().await.unwrap_or_default(),
+            })
         }
     }
 }
 
+impl Client {
+    fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        match &self.auth {
+            Some(auth) => auth.apply(request),
+            None => Ok(request),
+        }
+    }
+
+    async fn send_idempotent(&self, request: RequestBuilder) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let Some(next) = request.try_clone() else {
+                // streaming bodies cannot be retried
+                return self.authorize(request)?.send().await.map_err(Into::into);
+            };
+
+            let result = self.authorize(next)?.send().await;
+            let is_retryable = match &result {
+                Ok(response) => {
+                    response.status().is_server_error()
+                        || response.status() == StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(error) => error.is_connect() || error.is_timeout() || error.is_request(),
+            };
+            if !is_retryable || attempt >= self.retry.max_retries {
+                return result.map_err(Into::into);
+            }
+
+            sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+async fn decode<T>(response: Response) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let body = response.bytes().await?;
+    ::serde_json::from_slice(&body).map_err(Into::into)
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "status")]
 pub enum QueryResponse<Data = QueryResult> {
-    Success { data: Data },
+    Success {
+        data: Data,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+    },
+    Error {
+        #[serde(rename = "errorType")]
+        error_type: PrometheusErrorType,
+        error: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "resultType", content = "result")]
-pub enum QueryResult<Data = QueryData> {
+pub enum QueryResult<Data = QueryData, RangeData = QueryRangeData> {
     Vector(Vec<Data>),
+    Matrix(Vec<RangeData>),
+    /// `[timestamp, value]`
+    Scalar(#[serde(deserialize_with = "deserialize_value_vec")] Vec<f64>),
+    String(QueryString),
+}
+
+impl<Data, RangeData> QueryResult<Data, RangeData> {
+    pub const fn result_type(&self) -> &'static str {
+        match self {
+            Self::Vector(_) => "vector",
+            Self::Matrix(_) => "matrix",
+            Self::Scalar(_) => "scalar",
+            Self::String(_) => "string",
+        }
+    }
 }
 
+/// `[timestamp, value]`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueryString(pub f64, pub String);
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryData<Value = f64>
@@ -167,6 +598,18 @@ where
     pub value: Vec<Value>,
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRangeData<Value = f64>
+where
+    Value: FromStr + DeserializeOwned,
+    <Value as FromStr>::Err: ::std::error::Error,
+{
+    pub metric: QueryMetric,
+    #[serde(deserialize_with = "deserialize_value_vec_vec")]
+    pub values: Vec<Vec<Value>>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct QueryMetric {
     #[serde(rename = "footprint_kind")]
@@ -179,6 +622,35 @@ pub struct QueryMetric {
         skip_serializing_if = "is_empty"
     )]
     pub namespace: Option<String>,
+    /// The other labels of the series, e.g. `instance` and `job`
+    #[serde(default, flatten)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl QueryMetric {
+    /// Returns the object and the labels telling its series apart, regardless of the metrics.
+    fn into_series(mut self) -> (DataRef, BTreeMap<String, String>) {
+        let mut labels = ::std::mem::take(&mut self.labels);
+        labels.remove("__name__");
+        (self.into(), labels)
+    }
+}
+
+impl From<QueryMetric> for DataRef {
+    fn from(
+        QueryMetric {
+            kind,
+            name,
+            namespace,
+            labels: _,
+        }: QueryMetric,
+    ) -> Self {
+        Self {
+            kind,
+            name,
+            namespace: namespace.filter(|namespace| !namespace.is_empty()),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -201,6 +673,25 @@ where
     })
 }
 
+fn deserialize_value_vec_vec<'de, D, T>(deserializer: D) -> Result<Vec<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + DeserializeOwned,
+    <T as FromStr>::Err: ::std::error::Error,
+{
+    Deserialize::deserialize(deserializer).map(|values: Vec<Vec<ValueOrString<T>>>| {
+        values
+            .into_iter()
+            .map(|values| {
+                values
+                    .into_iter()
+                    .map(|ValueOrString(value)| value)
+                    .collect()
+            })
+            .collect()
+    })
+}
+
 fn deserialize_value<'de, D, T>(deserializer: D) -> Result<T, D::Error>
 where
     D: Deserializer<'de>,
@@ -226,3 +717,95 @@ fn is_empty(value: &Option<String>) -> bool {
         None => true,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Serves a Prometheus with two series of an object, the newer one listed first.
+    fn serve_replicas() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut line = String::new();
+                BufReader::new(stream.try_clone().unwrap())
+                    .read_line(&mut line)
+                    .unwrap();
+                let path = line.split_whitespace().nth(1).unwrap();
+                let url = Url::parse(&format!("http://localhost{path}")).unwrap();
+                let (_, query) = url.query_pairs().find(|(key, _)| key == "query").unwrap();
+
+                let series = |instance: &str, value: f64| {
+                    json!({
+                        "metric": {
+                            "__name__": "ignored",
+                            "footprint_kind": "user",
+                            "footprint_name": "a",
+                            "instance": instance,
+                        },
+                        "value": [0, value.to_string()],
+                    })
+                };
+                let result = if query.starts_with("timestamp(") {
+                    vec![series("new", 200.0), series("old", 100.0)]
+                } else if [
+                    consts::METRIC_ERROR_M,
+                    consts::METRIC_LATITUDE,
+                    consts::METRIC_LONGITUDE,
+                ]
+                .iter()
+                .any(|metric| query.starts_with(metric))
+                {
+                    vec![series("new", 2.0), series("old", 1.0)]
+                } else {
+                    vec![]
+                };
+
+                let body = json!({
+                    "status": "success",
+                    "data": {
+                        "resultType": "vector",
+                        "result": result,
+                    },
+                })
+                .to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len(),
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn take_newest_series() {
+        let client = Client::new(serve_replicas()).unwrap();
+        let locations = client.list_timed(&DataRefFilter::default()).await.unwrap();
+
+        assert_eq!(locations.len(), 1);
+        let location = &locations[&DataRef {
+            kind: "user".into(),
+            name: "a".into(),
+            namespace: None,
+        }];
+        assert_eq!(location.timestamp.timestamp(), 200);
+        assert_eq!(location.location.global.latitude, 2.0);
+        assert_eq!(location.location.global.longitude, 2.0);
+    }
+}
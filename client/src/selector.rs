@@ -0,0 +1,228 @@
+use std::fmt;
+
+use footprint_api::{DataRef, DataRefFilter};
+use footprint_provider_api::consts;
+
+/// A PromQL instant vector selector, e.g. `metric{label="value"}`.
+///
+/// The metric and label names are written as-is, while the label values are escaped
+/// following the PromQL string literal rules.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selector {
+    metric: Option<String>,
+    matchers: Vec<LabelMatcher>,
+}
+
+impl Selector {
+    pub fn new(metric: impl Into<String>) -> Self {
+        Self {
+            metric: Some(metric.into()),
+            matchers: Vec::default(),
+        }
+    }
+
+    pub fn with_metric(mut self, metric: impl Into<String>) -> Self {
+        self.metric = Some(metric.into());
+        self
+    }
+
+    pub fn with(mut self, matcher: LabelMatcher) -> Self {
+        self.matchers.push(matcher);
+        self
+    }
+
+    pub fn eq(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.with(LabelMatcher::new(name, MatchOp::Equal, value))
+    }
+
+    pub fn ne(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.with(LabelMatcher::new(name, MatchOp::NotEqual, value))
+    }
+
+    pub fn re(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.with(LabelMatcher::new(name, MatchOp::RegexMatch, value))
+    }
+
+    pub fn not_re(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.with(LabelMatcher::new(name, MatchOp::RegexNotMatch, value))
+    }
+
+    /// Selects exactly the given object.
+    pub fn with_data(
+        self,
+        DataRef {
+            kind,
+            name,
+            namespace,
+        }: &DataRef,
+    ) -> Self {
+        self.eq(consts::LABEL_KIND, kind)
+            .eq(consts::LABEL_NAME, name)
+            .eq(
+                consts::LABEL_NAMESPACE,
+                namespace.as_deref().unwrap_or_default(),
+            )
+    }
+
+    /// Selects all objects matching the given patterns.
+    pub fn with_filter(
+        self,
+        DataRefFilter {
+            kind,
+            name,
+            namespace,
+        }: &DataRefFilter,
+    ) -> Self {
+        [
+            (consts::LABEL_KIND, kind),
+            (consts::LABEL_NAME, name),
+            (consts::LABEL_NAMESPACE, namespace),
+        ]
+        .into_iter()
+        .filter_map(|(label, pattern)| pattern.as_ref().map(|pattern| (label, pattern)))
+        .fold(self, |selector, (label, pattern)| {
+            selector.re(label, pattern)
+        })
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(metric) = &self.metric {
+            f.write_str(metric)?;
+        }
+        if self.metric.is_none() || !self.matchers.is_empty() {
+            f.write_str("{")?;
+            for (index, matcher) in self.matchers.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(",")?;
+                }
+                matcher.fmt(f)?;
+            }
+            f.write_str("}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelMatcher {
+    pub name: String,
+    pub op: MatchOp,
+    pub value: String,
+}
+
+impl LabelMatcher {
+    pub fn new(name: impl Into<String>, op: MatchOp, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            op,
+            value: value.into(),
+        }
+    }
+}
+
+impl fmt::Display for LabelMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{name}{op}\"{value}\"",
+            name = &self.name,
+            op = self.op,
+            value = escape(&self.value),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MatchOp {
+    Equal,
+    NotEqual,
+    RegexMatch,
+    RegexNotMatch,
+}
+
+impl fmt::Display for MatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Equal => "=",
+            Self::NotEqual => "!=",
+            Self::RegexMatch => "=~",
+            Self::RegexNotMatch => "!~",
+        })
+    }
+}
+
+/// Escapes a value to be placed in a double-quoted PromQL string literal.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_values() {
+        for (value, expected) in [
+            ("plain", "plain"),
+            (r#"a"b"#, r#"a\"b"#),
+            (r"a\b", r"a\\b"),
+            (r#"\""#, r#"\\\""#),
+            ("a\nb", r"a\nb"),
+            ("a\r\tb", r"a\r\tb"),
+            ("a\u{0}b\u{1b}", r"a\u0000b\u001b"),
+            ("a\u{7f}\u{85}", r"a\u007f\u0085"),
+            ("사용자-ü-🦀", "사용자-ü-🦀"),
+        ] {
+            assert_eq!(escape(value), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn display_selectors() {
+        for (selector, expected) in [
+            (Selector::default(), "{}"),
+            (Selector::new("metric"), "metric"),
+            (Selector::new("metric").eq("a", "1"), r#"metric{a="1"}"#),
+            (
+                Selector::default()
+                    .eq("a", r#"x"y"#)
+                    .ne("b", "x\ny")
+                    .re("c", r"x\.y")
+                    .not_re("d", "사용자"),
+                r#"{a="x\"y",b!="x\ny",c=~"x\\.y",d!~"사용자"}"#,
+            ),
+        ] {
+            assert_eq!(selector.to_string(), expected);
+        }
+
+        // the values cannot escape their string literals
+        let selector = Selector::new("metric").with_data(&DataRef {
+            kind: "users.vine.ulagbulag.io/v1alpha1".into(),
+            name: r#"evil"} or vector(1) #"#.into(),
+            namespace: None,
+        });
+        assert_eq!(
+            selector.to_string(),
+            format!(
+                r#"metric{{{kind}="users.vine.ulagbulag.io/v1alpha1",{name}="evil\"}} or vector(1) #",{namespace}=""}}"#,
+                kind = consts::LABEL_KIND,
+                name = consts::LABEL_NAME,
+                namespace = consts::LABEL_NAMESPACE,
+            ),
+        );
+    }
+}
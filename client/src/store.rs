@@ -0,0 +1,369 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use footprint_api::{
+    DataRef, DataRefFilter, DistanceMetric, GlobalLocation, Location, NearbyObject,
+    ProximityCenter, ProximityQuery, TimedLocation,
+};
+use regex::Regex;
+
+use crate::{Client, Error, Result};
+
+pub use self::{
+    memory::MemoryStore,
+    segment::{SegmentStore, SegmentStoreConfig},
+};
+
+mod memory;
+mod segment;
+
+/// A storage of the object locations.
+#[async_trait]
+pub trait LocationStore: Send + Sync {
+    /// Returns the latest location of the object.
+    async fn get(&self, data: &DataRef) -> Result<Option<Location>>;
+
+    /// Returns the latest locations of all objects matching the filter.
+    async fn list(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, Location>> {
+        self.list_timed(filter).await.map(|locations| {
+            locations
+                .into_iter()
+                .map(|(data, location)| (data, location.location))
+                .collect()
+        })
+    }
+
+    /// Returns the latest locations of all objects matching the filter, with their timestamps.
+    async fn list_timed(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, TimedLocation>>;
+
+    /// Returns the latest locations as of `time` of all objects matching the filter,
+    /// skipping the ones not observed within the lookback of the store.
+    async fn list_timed_at(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>>;
+
+    /// Returns whether the store keeps the local coordinates of the objects.
+    fn has_local_coordinates(&self) -> bool {
+        true
+    }
+
+    /// Returns the objects within the radius from the center, sorted by the distance.
+    async fn nearby(&self, query: &ProximityQuery) -> Result<Vec<NearbyObject>> {
+        let (center, metric, exclude) = match &query.center {
+            ProximityCenter::Global(global) => (
+                Location {
+                    global: *global,
+                    local: Default::default(),
+                    motion: Default::default(),
+                },
+                DistanceMetric::Global,
+                None,
+            ),
+            ProximityCenter::Local(local) => (
+                Location {
+                    global: GlobalLocation {
+                        error_m: f64::NAN,
+                        latitude: f64::NAN,
+                        longitude: f64::NAN,
+                    },
+                    local: *local,
+                    motion: Default::default(),
+                },
+                DistanceMetric::Local,
+                None,
+            ),
+            ProximityCenter::Object { data, metric } => (
+                self.get(data).await?.ok_or(Error::NotFound)?,
+                *metric,
+                Some(data),
+            ),
+        };
+        if metric == DistanceMetric::Local && !self.has_local_coordinates() {
+            return Err(Error::Unsupported("the store has no local coordinates"));
+        }
+
+        let mut objects: Vec<_> = self
+            .list_timed(&query.filter)
+            .await?
+            .into_iter()
+            .filter(|(data, _)| Some(data) != exclude)
+            .filter_map(|(data, location)| {
+                let (distance_m, error_m) = match metric {
+                    DistanceMetric::Global => (
+                        center.global.distance_m(&location.location.global),
+                        center.global.error_m + location.location.global.error_m,
+                    ),
+                    DistanceMetric::Local => (
+                        center.local.distance_m(&location.location.local),
+                        center.local.error_m + location.location.local.error_m,
+                    ),
+                };
+                let distance_m = if query.with_error && error_m.is_finite() {
+                    (distance_m - error_m).max(0.0)
+                } else {
+                    distance_m
+                };
+
+                // NaN if the object has no such location
+                (distance_m <= query.radius_m).then_some(NearbyObject {
+                    data,
+                    location,
+                    distance_m,
+                })
+            })
+            .collect();
+        objects.sort_by(|a, b| a.distance_m.total_cmp(&b.distance_m));
+        Ok(objects)
+    }
+
+    /// Returns the trajectory of the object, sampled by `step`.
+    async fn history(
+        &self,
+        data: &DataRef,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<TimedLocation>>;
+
+    async fn put(&self, data: &DataRef, location: &TimedLocation) -> Result<()>;
+}
+
+#[async_trait]
+impl LocationStore for Client {
+    async fn get(&self, data: &DataRef) -> Result<Option<Location>> {
+        self.get_raw(data).await
+    }
+
+    async fn list(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, Location>> {
+        Client::list(self, filter).await
+    }
+
+    async fn list_timed(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        Client::list_timed(self, filter).await
+    }
+
+    /// The providers do not export the local coordinates to Prometheus.
+    fn has_local_coordinates(&self) -> bool {
+        false
+    }
+
+    async fn list_timed_at(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        Client::list_timed_at(self, filter, time).await
+    }
+
+    async fn history(
+        &self,
+        data: &DataRef,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<TimedLocation>> {
+        Client::history(self, data, start, end, step).await
+    }
+
+    async fn put(&self, _data: &DataRef, _location: &TimedLocation) -> Result<()> {
+        Err(Error::Unsupported(
+            "prometheus is read-only; push the locations to the providers instead",
+        ))
+    }
+}
+
+/// A compiled [`DataRefFilter`], following the PromQL `=~` semantics.
+#[derive(Clone, Debug, Default)]
+pub struct DataRefMatcher {
+    kind: Option<Regex>,
+    name: Option<Regex>,
+    namespace: Option<Regex>,
+}
+
+impl DataRefMatcher {
+    pub fn new(filter: &DataRefFilter) -> Result<Self> {
+        fn compile(pattern: &Option<String>) -> Result<Option<Regex>> {
+            pattern
+                .as_ref()
+                .map(|pattern| Regex::new(&format!("^(?:{pattern})$")))
+                .transpose()
+                .map_err(Into::into)
+        }
+
+        Ok(Self {
+            kind: compile(&filter.kind)?,
+            name: compile(&filter.name)?,
+            namespace: compile(&filter.namespace)?,
+        })
+    }
+
+    pub fn is_match(&self, data: &DataRef) -> bool {
+        fn is_match(pattern: &Option<Regex>, value: &str) -> bool {
+            match pattern {
+                Some(pattern) => pattern.is_match(value),
+                None => true,
+            }
+        }
+
+        is_match(&self.kind, &data.kind)
+            && is_match(&self.name, &data.name)
+            && is_match(
+                &self.namespace,
+                data.namespace.as_deref().unwrap_or_default(),
+            )
+    }
+}
+
+/// The maximum resolution of a trajectory, following the limit of Prometheus
+/// (`(end - start) / step`).
+pub const MAX_HISTORY_POINTS: u128 = 11_000;
+
+/// Fails if the step is zero, or the trajectory sampled by `step` would have too many points.
+pub fn check_history_points(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    step: Duration,
+) -> Result<()> {
+    if step.is_zero() {
+        return Err(Error::ZeroStep);
+    }
+    let span = (end - start).to_std().unwrap_or_default();
+    let points = span.as_nanos() / step.as_nanos();
+    if points > MAX_HISTORY_POINTS {
+        return Err(Error::TooManyPoints { points });
+    }
+    Ok(())
+}
+
+/// Samples the latest location at each step, like a Prometheus range query.
+pub(crate) fn sample(
+    samples: impl IntoIterator<Item = TimedLocation>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    step: Duration,
+    lookback: Duration,
+) -> Vec<TimedLocation> {
+    let step = ::chrono::Duration::from_std(step).unwrap_or_default();
+    let lookback = ::chrono::Duration::from_std(lookback).unwrap_or_default();
+    if step <= ::chrono::Duration::zero() || start > end {
+        return Vec::default();
+    }
+
+    let mut samples = samples.into_iter().peekable();
+    let mut last: Option<TimedLocation> = None;
+    let mut trajectory = Vec::default();
+
+    let mut timestamp = start;
+    while timestamp <= end {
+        while let Some(sample) = samples.next_if(|sample| sample.timestamp <= timestamp) {
+            last = Some(sample);
+        }
+        if let Some(sample) = last.filter(|sample| timestamp - sample.timestamp <= lookback) {
+            trajectory.push(TimedLocation {
+                timestamp,
+                location: sample.location,
+            });
+        }
+        timestamp += step;
+    }
+    trajectory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(kind: &str, name: &str, namespace: Option<&str>) -> DataRef {
+        DataRef {
+            kind: kind.into(),
+            name: name.into(),
+            namespace: namespace.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn match_anchored() {
+        let matcher = DataRefMatcher::new(&DataRefFilter {
+            kind: Some("user".into()),
+            name: Some("a|b".into()),
+            namespace: None,
+        })
+        .unwrap();
+
+        assert!(matcher.is_match(&data("user", "a", None)));
+        assert!(matcher.is_match(&data("user", "b", Some("vine"))));
+        // the patterns are anchored at both ends, like PromQL
+        assert!(!matcher.is_match(&data("users", "a", None)));
+        assert!(!matcher.is_match(&data("user", "ab", None)));
+        assert!(!matcher.is_match(&data("user", "xa", None)));
+        assert!(!matcher.is_match(&data("superuser", "a", None)));
+    }
+
+    #[test]
+    fn match_namespace() {
+        let matcher = DataRefMatcher::new(&DataRefFilter {
+            namespace: Some("vine|".into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert!(matcher.is_match(&data("user", "a", Some("vine"))));
+        // the cluster-scoped objects have the empty namespace
+        assert!(matcher.is_match(&data("user", "a", None)));
+        assert!(!matcher.is_match(&data("user", "a", Some("vine-dev"))));
+    }
+
+    #[test]
+    fn match_all_by_default() {
+        let matcher = DataRefMatcher::new(&DataRefFilter::default()).unwrap();
+        assert!(matcher.is_match(&data("user", "a", Some("vine"))));
+    }
+
+    #[test]
+    fn limit_history_points() {
+        let start = DateTime::UNIX_EPOCH;
+        let step = Duration::from_secs(1);
+        let max = MAX_HISTORY_POINTS as i64;
+
+        let end = start + ::chrono::Duration::seconds(max);
+        assert!(check_history_points(start, end, step).is_ok());
+        let end = start + ::chrono::Duration::seconds(max + 1);
+        assert!(matches!(
+            check_history_points(start, end, step),
+            Err(Error::TooManyPoints { points }) if points == MAX_HISTORY_POINTS + 1,
+        ));
+        assert!(matches!(
+            check_history_points(start, start, Duration::ZERO),
+            Err(Error::ZeroStep),
+        ));
+    }
+
+    #[tokio::test]
+    async fn reject_local_nearby_on_prometheus() {
+        let client = Client::builder("http://localhost:9090".parse().unwrap())
+            .build()
+            .unwrap();
+        let query = ProximityQuery {
+            center: ProximityCenter::Local(Default::default()),
+            radius_m: 1.0,
+            with_error: false,
+            filter: DataRefFilter::default(),
+        };
+        assert!(matches!(
+            client.nearby(&query).await,
+            Err(Error::Unsupported(_)),
+        ));
+    }
+
+    #[test]
+    fn reject_invalid_pattern() {
+        let filter = DataRefFilter {
+            name: Some("(".into()),
+            ..Default::default()
+        };
+        assert!(DataRefMatcher::new(&filter).is_err());
+    }
+}
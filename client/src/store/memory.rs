@@ -0,0 +1,371 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use footprint_api::{DataRef, DataRefFilter, Location, TimedLocation};
+use tokio::sync::RwLock;
+
+use super::{check_history_points, sample, DataRefMatcher, LocationStore};
+use crate::Result;
+
+/// A volatile storage, keeping the recent locations of each object in memory.
+///
+/// Like Prometheus, the latest locations older than the lookback are stale,
+/// and the objects not updated within the retention are evicted.
+#[derive(Debug)]
+pub struct MemoryStore {
+    lookback: Duration,
+    retention: Duration,
+    max_samples: usize,
+    objects: RwLock<BTreeMap<DataRef, VecDeque<TimedLocation>>>,
+    evicted_at: Mutex<DateTime<Utc>>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new(4096)
+    }
+}
+
+impl MemoryStore {
+    pub fn new(max_samples: usize) -> Self {
+        Self {
+            // same as the default lookback delta of Prometheus
+            lookback: Duration::from_secs(5 * 60),
+            retention: Duration::from_secs(60 * 60),
+            max_samples: max_samples.max(1),
+            objects: RwLock::default(),
+            evicted_at: Mutex::new(Utc::now()),
+        }
+    }
+
+    /// Sets how far back the samples are looked up when sampling a history,
+    /// also hiding the latest locations older than it.
+    pub fn with_lookback(mut self, lookback: Duration) -> Self {
+        self.lookback = lookback;
+        self
+    }
+
+    /// Sets how long the samples are kept, evicting the objects without the newer ones.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    fn latest(
+        &self,
+        samples: &VecDeque<TimedLocation>,
+        now: DateTime<Utc>,
+    ) -> Option<TimedLocation> {
+        let lookback =
+            ::chrono::Duration::from_std(self.lookback).unwrap_or(::chrono::Duration::MAX);
+        samples
+            .back()
+            .filter(|sample| now - sample.timestamp <= lookback)
+            .copied()
+    }
+
+    /// Drops the samples older than the retention, at most once per lookback.
+    fn evict(&self, objects: &mut BTreeMap<DataRef, VecDeque<TimedLocation>>) {
+        let now = Utc::now();
+        {
+            let mut evicted_at = self.evicted_at.lock().unwrap();
+            let lookback =
+                ::chrono::Duration::from_std(self.lookback).unwrap_or(::chrono::Duration::MAX);
+            if now - *evicted_at < lookback {
+                return;
+            }
+            *evicted_at = now;
+        }
+
+        let retention =
+            ::chrono::Duration::from_std(self.retention).unwrap_or(::chrono::Duration::MAX);
+        objects.retain(|_, samples| {
+            while samples
+                .front()
+                .is_some_and(|sample| now - sample.timestamp > retention)
+            {
+                samples.pop_front();
+            }
+            !samples.is_empty()
+        });
+    }
+}
+
+#[async_trait]
+impl LocationStore for MemoryStore {
+    async fn get(&self, data: &DataRef) -> Result<Option<Location>> {
+        let now = Utc::now();
+        Ok(self
+            .objects
+            .read()
+            .await
+            .get(data)
+            .and_then(|samples| self.latest(samples, now))
+            .map(|sample| sample.location))
+    }
+
+    async fn list_timed(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let matcher = DataRefMatcher::new(filter)?;
+        let now = Utc::now();
+
+        Ok(self
+            .objects
+            .read()
+            .await
+            .iter()
+            .filter(|(data, _)| matcher.is_match(data))
+            .filter_map(|(data, samples)| Some((data.clone(), self.latest(samples, now)?)))
+            .collect())
+    }
+
+    async fn list_timed_at(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let matcher = DataRefMatcher::new(filter)?;
+        let lookback = ::chrono::Duration::from_std(self.lookback).unwrap_or_default();
+
+        Ok(self
+            .objects
+            .read()
+            .await
+            .iter()
+            .filter(|(data, _)| matcher.is_match(data))
+            .filter_map(|(data, samples)| {
+                let index = samples.partition_point(|sample| sample.timestamp <= time);
+                let sample = samples.get(index.checked_sub(1)?)?;
+                (time - sample.timestamp <= lookback).then(|| (data.clone(), *sample))
+            })
+            .collect())
+    }
+
+    async fn history(
+        &self,
+        data: &DataRef,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<TimedLocation>> {
+        check_history_points(start, end, step)?;
+        let objects = self.objects.read().await;
+        let samples = match objects.get(data) {
+            Some(samples) => samples,
+            None => return Ok(Vec::default()),
+        };
+        Ok(sample(
+            samples.iter().copied(),
+            start,
+            end,
+            step,
+            self.lookback,
+        ))
+    }
+
+    async fn put(&self, data: &DataRef, location: &TimedLocation) -> Result<()> {
+        let mut objects = self.objects.write().await;
+        let samples = objects.entry(data.clone()).or_default();
+
+        // keep the samples ordered by their timestamps
+        let index = samples.partition_point(|sample| sample.timestamp <= location.timestamp);
+        samples.insert(index, *location);
+
+        while samples.len() > self.max_samples {
+            samples.pop_front();
+        }
+
+        self.evict(&mut objects);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use footprint_api::{GlobalLocation, LocalLocation, Motion};
+
+    use super::*;
+
+    fn data(name: &str) -> DataRef {
+        DataRef {
+            kind: "user".into(),
+            name: name.into(),
+            namespace: Some("vine".into()),
+        }
+    }
+
+    fn at(sec: i64, latitude: f64) -> TimedLocation {
+        TimedLocation {
+            timestamp: DateTime::from_timestamp(sec, 0).unwrap(),
+            location: Location {
+                global: GlobalLocation {
+                    error_m: 1.0,
+                    latitude,
+                    longitude: 127.0,
+                },
+                local: LocalLocation::default(),
+                motion: Motion::default(),
+            },
+        }
+    }
+
+    /// Returns the location observed the given seconds ago.
+    fn ago(sec: i64, latitude: f64) -> TimedLocation {
+        at(Utc::now().timestamp() - sec, latitude)
+    }
+
+    #[tokio::test]
+    async fn put_and_get() {
+        let store = MemoryStore::default();
+        assert_eq!(store.get(&data("a")).await.unwrap(), None);
+
+        let (a, b) = (ago(10, 2.0), ago(20, 4.0));
+        store.put(&data("a"), &ago(20, 1.0)).await.unwrap();
+        store.put(&data("a"), &a).await.unwrap();
+        // a late sample does not replace the latest one
+        store.put(&data("a"), &ago(15, 3.0)).await.unwrap();
+        store.put(&data("b"), &b).await.unwrap();
+
+        let location = store.get(&data("a")).await.unwrap().unwrap();
+        assert_eq!(location.global.latitude, 2.0);
+
+        let locations = store.list_timed(&DataRefFilter::default()).await.unwrap();
+        assert_eq!(locations.len(), 2);
+        assert_eq!(locations[&data("a")], a);
+        assert_eq!(locations[&data("b")], b);
+
+        let filter = DataRefFilter {
+            name: Some("b".into()),
+            ..Default::default()
+        };
+        let locations = store.list(&filter).await.unwrap();
+        assert_eq!(locations.keys().collect::<Vec<_>>(), [&data("b")]);
+    }
+
+    #[tokio::test]
+    async fn history_in_order() {
+        let store = MemoryStore::default();
+        for (sec, latitude) in [(30, 3.0), (10, 1.0), (20, 2.0)] {
+            store.put(&data("a"), &at(sec, latitude)).await.unwrap();
+        }
+
+        let history = store
+            .history(
+                &data("a"),
+                DateTime::from_timestamp(5, 0).unwrap(),
+                DateTime::from_timestamp(35, 0).unwrap(),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(history, [at(15, 1.0), at(25, 2.0), at(35, 3.0)]);
+
+        let history = store
+            .history(
+                &data("b"),
+                DateTime::from_timestamp(0, 0).unwrap(),
+                DateTime::from_timestamp(35, 0).unwrap(),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn history_with_lookback() {
+        let store = MemoryStore::default().with_lookback(Duration::from_secs(10));
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+
+        let history = store
+            .history(
+                &data("a"),
+                DateTime::from_timestamp(0, 0).unwrap(),
+                DateTime::from_timestamp(30, 0).unwrap(),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(history, [at(10, 1.0), at(20, 1.0)]);
+    }
+
+    #[tokio::test]
+    async fn list_at() {
+        let store = MemoryStore::default().with_lookback(Duration::from_secs(10));
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        store.put(&data("a"), &at(20, 2.0)).await.unwrap();
+        store.put(&data("b"), &at(5, 3.0)).await.unwrap();
+
+        let list_at = |sec| {
+            let store = &store;
+            async move {
+                store
+                    .list_timed_at(
+                        &DataRefFilter::default(),
+                        DateTime::from_timestamp(sec, 0).unwrap(),
+                    )
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(list_at(4).await, []);
+        assert_eq!(
+            list_at(12).await,
+            [(data("a"), at(10, 1.0)), (data("b"), at(5, 3.0))],
+        );
+        // the stale ones are skipped
+        assert_eq!(list_at(25).await, [(data("a"), at(20, 2.0))]);
+    }
+
+    #[tokio::test]
+    async fn evict_oldest() {
+        let store = MemoryStore::new(2);
+        for (sec, latitude) in [(20, 2.0), (30, 3.0), (10, 1.0), (40, 4.0)] {
+            store.put(&data("a"), &at(sec, latitude)).await.unwrap();
+        }
+
+        let history = store
+            .history(
+                &data("a"),
+                DateTime::from_timestamp(0, 0).unwrap(),
+                DateTime::from_timestamp(40, 0).unwrap(),
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(history, [at(30, 3.0), at(40, 4.0)]);
+    }
+
+    #[tokio::test]
+    async fn hide_stale() {
+        let store = MemoryStore::default().with_lookback(Duration::from_secs(60));
+        store.put(&data("a"), &ago(120, 1.0)).await.unwrap();
+        store.put(&data("b"), &ago(10, 2.0)).await.unwrap();
+
+        assert_eq!(store.get(&data("a")).await.unwrap(), None);
+        assert!(store.get(&data("b")).await.unwrap().is_some());
+        let locations = store.list_timed(&DataRefFilter::default()).await.unwrap();
+        assert_eq!(locations.keys().collect::<Vec<_>>(), [&data("b")]);
+    }
+
+    #[tokio::test]
+    async fn evict_retired() {
+        let store = MemoryStore::default()
+            .with_lookback(Duration::ZERO)
+            .with_retention(Duration::from_secs(60));
+        store.put(&data("a"), &ago(120, 1.0)).await.unwrap();
+        store.put(&data("a"), &ago(30, 2.0)).await.unwrap();
+        store.put(&data("b"), &ago(90, 3.0)).await.unwrap();
+        store.put(&data("c"), &ago(10, 4.0)).await.unwrap();
+
+        let objects = store.objects.read().await;
+        assert_eq!(objects.keys().collect::<Vec<_>>(), [&data("a"), &data("c")]);
+        assert_eq!(objects[&data("a")].len(), 1);
+    }
+}
@@ -0,0 +1,931 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use footprint_api::{DataRef, DataRefFilter, Location, TimedLocation};
+use serde::{Deserialize, Serialize};
+
+use super::{check_history_points, sample, DataRefMatcher, LocationStore};
+use crate::{Error, Result};
+
+const EXTENSION_RAW: &str = ".jsonl";
+const EXTENSION_DOWNSAMPLED: &str = ".downsampled.jsonl";
+
+/// The number of the latest raw segments to keep parsed in memory.
+const MAX_CACHED_SEGMENTS: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SegmentStoreConfig {
+    pub path: PathBuf,
+    /// Time span covered by each segment file
+    pub segment: Duration,
+    /// Segments older than this are removed
+    pub retention: Option<Duration>,
+    /// Segments older than this are downsampled to one sample per object per `downsample_step`
+    pub downsample_after: Option<Duration>,
+    pub downsample_step: Duration,
+    /// How far back the samples are looked up when sampling a history
+    pub lookback: Duration,
+    /// The written records are fsynced within this, and on every segment rotation;
+    /// zero to fsync every record
+    pub sync_interval: Duration,
+}
+
+impl SegmentStoreConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            segment: Duration::from_secs(60 * 60),
+            retention: None,
+            downsample_after: None,
+            downsample_step: Duration::from_secs(60),
+            lookback: Duration::from_secs(5 * 60),
+            sync_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A persistent storage, appending every location to hourly (by default) JSON-lines segments.
+#[derive(Clone, Debug)]
+pub struct SegmentStore {
+    inner: Arc<Inner>,
+}
+
+impl SegmentStore {
+    pub fn open(config: SegmentStoreConfig) -> Result<Self> {
+        fs::create_dir_all(&config.path).map_err(|source| Error::Io {
+            path: config.path.clone(),
+            source,
+        })?;
+
+        let inner = Inner {
+            state: Mutex::new(State::default()),
+            latest: Mutex::default(),
+            cache: Mutex::default(),
+            config,
+        };
+        inner.load()?;
+        inner.maintain()?;
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    /// Returns all stored records of the matching objects within the time range.
+    pub async fn records(
+        &self,
+        filter: &DataRefFilter,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<(DataRef, TimedLocation)>> {
+        let matcher = DataRefMatcher::new(filter)?;
+        self.spawn(move |inner| inner.read(start, end, |data| matcher.is_match(data)))
+            .await
+    }
+
+    /// Applies the retention and downsampling policies.
+    pub async fn maintain(&self) -> Result<()> {
+        self.spawn(|inner| inner.maintain()).await
+    }
+
+    /// Fsyncs the written records, to be called periodically as the writes may pause.
+    pub async fn sync(&self) -> Result<()> {
+        self.spawn(|inner| inner.sync(&mut inner.state.lock().unwrap()))
+            .await
+    }
+
+    async fn spawn<F, T>(&self, f: F) -> Result<T>
+    where
+        F: 'static + Send + FnOnce(&Inner) -> Result<T>,
+        T: 'static + Send,
+    {
+        let inner = self.inner.clone();
+        match ::tokio::task::spawn_blocking(move || f(&inner)).await {
+            Ok(result) => result,
+            Err(error) => ::std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+}
+
+#[async_trait]
+impl LocationStore for SegmentStore {
+    async fn get(&self, data: &DataRef) -> Result<Option<Location>> {
+        let latest = self.inner.latest.lock().unwrap();
+        Ok(latest.get(data).map(|sample| sample.location))
+    }
+
+    async fn list_timed(&self, filter: &DataRefFilter) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let matcher = DataRefMatcher::new(filter)?;
+
+        let latest = self.inner.latest.lock().unwrap();
+        Ok(latest
+            .iter()
+            .filter(|(data, _)| matcher.is_match(data))
+            .map(|(data, sample)| (data.clone(), *sample))
+            .collect())
+    }
+
+    async fn list_timed_at(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let matcher = DataRefMatcher::new(filter)?;
+        self.spawn(move |inner| {
+            let lookback = ::chrono::Duration::from_std(inner.config.lookback).unwrap_or_default();
+            // the records are sorted by the timestamps, so the latest ones remain
+            Ok(inner
+                .read(time - lookback, time, |data| matcher.is_match(data))?
+                .into_iter()
+                .collect())
+        })
+        .await
+    }
+
+    async fn history(
+        &self,
+        data: &DataRef,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: Duration,
+    ) -> Result<Vec<TimedLocation>> {
+        check_history_points(start, end, step)?;
+
+        let data = data.clone();
+        self.spawn(move |inner| {
+            let lookback = ::chrono::Duration::from_std(inner.config.lookback).unwrap_or_default();
+            let samples = inner
+                .read(start - lookback, end, |record| record == &data)?
+                .into_iter()
+                .map(|(_, sample)| sample);
+            Ok(sample(samples, start, end, step, inner.config.lookback))
+        })
+        .await
+    }
+
+    async fn put(&self, data: &DataRef, location: &TimedLocation) -> Result<()> {
+        let record = Record {
+            data: data.clone(),
+            location: *location,
+        };
+        self.spawn(move |inner| inner.write(record)).await
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: SegmentStoreConfig,
+    /// Locked while accessing the files, which may take long, e.g. on downsampling
+    state: Mutex<State>,
+    /// Locked only shortly, not to block the async readers on the files
+    latest: Mutex<BTreeMap<DataRef, TimedLocation>>,
+    /// The parsed records of the latest raw segments, which are read repeatedly,
+    /// e.g. by the streams looking up the delayed locations every second
+    cache: Mutex<BTreeMap<i64, CachedSegment>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    segments: BTreeMap<i64, Segment>,
+    writer: Option<(i64, BufWriter<File>)>,
+    /// When the oldest record not fsynced yet was written
+    unsynced_since: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct CachedSegment {
+    /// The length of the parsed complete lines, from which the appended ones are parsed
+    len: u64,
+    records: Vec<Record>,
+}
+
+#[derive(Clone, Debug)]
+struct Segment {
+    path: PathBuf,
+    downsampled: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Record {
+    #[serde(flatten)]
+    data: DataRef,
+    #[serde(flatten)]
+    location: TimedLocation,
+}
+
+impl Inner {
+    fn segment_millis(&self) -> i64 {
+        (self.config.segment.as_millis() as i64).max(1)
+    }
+
+    fn segment_start(&self, timestamp: DateTime<Utc>) -> i64 {
+        timestamp
+            .timestamp_millis()
+            .div_euclid(self.segment_millis())
+            * self.segment_millis()
+    }
+
+    fn load(&self) -> Result<()> {
+        let entries = fs::read_dir(&self.config.path).map_err(|source| Error::Io {
+            path: self.config.path.clone(),
+            source,
+        })?;
+
+        let mut state = self.state.lock().unwrap();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            let (start, downsampled) = match name.strip_suffix(EXTENSION_DOWNSAMPLED) {
+                Some(start) => (start, true),
+                None => match name.strip_suffix(EXTENSION_RAW) {
+                    Some(start) => (start, false),
+                    None => continue,
+                },
+            };
+            if let Ok(start) = start.parse() {
+                if !downsampled {
+                    repair(&path)?;
+                }
+                state.segments.insert(start, Segment { path, downsampled });
+            }
+        }
+
+        // rebuild the index of the latest locations
+        let mut latest = BTreeMap::default();
+        for segment in state.segments.values() {
+            for Record { data, location } in read_records(&segment.path)? {
+                update_latest(&mut latest, data, location);
+            }
+        }
+        *self.latest.lock().unwrap() = latest;
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        filter: impl Fn(&DataRef) -> bool,
+    ) -> Result<Vec<(DataRef, TimedLocation)>> {
+        let segments: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            if let Some((_, writer)) = state.writer.as_mut() {
+                writer.flush().map_err(|source| Error::Io {
+                    path: self.config.path.clone(),
+                    source,
+                })?;
+            }
+
+            let first = self.segment_start(start);
+            state
+                .segments
+                .range(first..=end.timestamp_millis())
+                .map(|(&start, segment)| (start, segment.clone()))
+                .collect()
+        };
+
+        let is_match = |record: &Record| {
+            record.location.timestamp >= start
+                && record.location.timestamp <= end
+                && filter(&record.data)
+        };
+        let mut records = Vec::default();
+        for (start, segment) in segments {
+            if segment.downsampled {
+                records.extend(
+                    read_records(&segment.path)?
+                        .into_iter()
+                        .filter(is_match)
+                        .map(|Record { data, location }| (data, location)),
+                );
+            } else {
+                self.read_cached(start, &segment.path, |record| {
+                    if is_match(record) {
+                        records.push((record.data.clone(), record.location));
+                    }
+                })?;
+            }
+        }
+        records.sort_by_key(|(_, location)| location.timestamp);
+        Ok(records)
+    }
+
+    /// Visits the records of the raw segment, parsing only the lines appended since the last.
+    fn read_cached(&self, start: i64, path: &Path, f: impl FnMut(&Record)) -> Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        let cached = cache.entry(start).or_default();
+        let len = fs::metadata(path)
+            .map_err(|source| Error::Io {
+                path: path.to_path_buf(),
+                source,
+            })?
+            .len();
+        if len < cached.len {
+            // rewritten by others
+            *cached = CachedSegment::default();
+        }
+        cached.len = read_records_from(path, cached.len, &mut cached.records)?;
+        cached.records.iter().for_each(f);
+
+        while cache.len() > MAX_CACHED_SEGMENTS {
+            cache.pop_first();
+        }
+        Ok(())
+    }
+
+    fn write(&self, record: Record) -> Result<()> {
+        let start = self.segment_start(record.location.timestamp);
+
+        let is_new_segment = {
+            let mut state = self.state.lock().unwrap();
+            let segment = state.segments.get(&start).cloned();
+            match &segment {
+                // merge the late samples into the downsampled segments, keeping them downsampled
+                Some(segment) if segment.downsampled => {
+                    let mut records = read_records(&segment.path)?;
+                    records.push(record.clone());
+                    write_records(&segment.path, &self.downsample(records))?;
+                }
+                _ => {
+                    if let Err(error) = self.append(&mut state, start, &record) {
+                        // reopen and repair the segment on the next write
+                        state.writer = None;
+                        return Err(error);
+                    }
+                }
+            }
+
+            let Record { data, location } = record;
+            update_latest(&mut self.latest.lock().unwrap(), data, location);
+            segment.is_none()
+        };
+
+        if is_new_segment {
+            self.maintain()?;
+        }
+        Ok(())
+    }
+
+    fn append(&self, state: &mut State, start: i64, record: &Record) -> Result<()> {
+        if state.writer.as_ref().map(|(current, _)| *current) != Some(start) {
+            // fsync the previous segment on rotation
+            self.sync(state)?;
+
+            let segment = state.segments.entry(start).or_insert_with(|| Segment {
+                path: self.config.path.join(format!("{start}{EXTENSION_RAW}")),
+                downsampled: false,
+            });
+            repair(&segment.path)?;
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&segment.path)
+                .map_err(|source| Error::Io {
+                    path: segment.path.clone(),
+                    source,
+                })?;
+            sync_dir(&self.config.path)?;
+            state.writer = Some((start, BufWriter::new(file)));
+        }
+
+        let (_, writer) = state.writer.as_mut().unwrap();
+        ::serde_json::to_writer(&mut *writer, record)?;
+        writer
+            .write_all(b"\n")
+            .and_then(|()| writer.flush())
+            .map_err(|source| Error::Io {
+                path: self.config.path.clone(),
+                source,
+            })?;
+
+        let unsynced_since = *state.unsynced_since.get_or_insert_with(Instant::now);
+        if unsynced_since.elapsed() >= self.config.sync_interval {
+            self.sync(state)?;
+        }
+        Ok(())
+    }
+
+    /// Fsyncs the records written to the current segment, if any.
+    fn sync(&self, state: &mut State) -> Result<()> {
+        if state.unsynced_since.is_none() {
+            return Ok(());
+        }
+        if let Some((_, writer)) = state.writer.as_mut() {
+            writer
+                .flush()
+                .and_then(|()| writer.get_ref().sync_data())
+                .map_err(|source| Error::Io {
+                    path: self.config.path.clone(),
+                    source,
+                })?;
+        }
+        state.unsynced_since = None;
+        Ok(())
+    }
+
+    /// Keeps the latest record of each object per `downsample_step`, sorted by the timestamps.
+    fn downsample(&self, records: impl IntoIterator<Item = Record>) -> Vec<Record> {
+        let step = (self.config.downsample_step.as_millis() as i64).max(1);
+
+        let mut buckets: HashMap<(DataRef, i64), Record> = HashMap::default();
+        for record in records {
+            let bucket = record
+                .location
+                .timestamp
+                .timestamp_millis()
+                .div_euclid(step);
+            let key = (record.data.clone(), bucket);
+            match buckets.get(&key) {
+                Some(last) if last.location.timestamp > record.location.timestamp => {}
+                _ => {
+                    buckets.insert(key, record);
+                }
+            }
+        }
+
+        let mut records: Vec<_> = buckets.into_values().collect();
+        records.sort_by_key(|record| record.location.timestamp);
+        records
+    }
+
+    fn maintain(&self) -> Result<()> {
+        let now = Utc::now();
+        let age = |duration: Duration| {
+            let duration = ::chrono::Duration::from_std(duration).unwrap_or_default();
+            self.segment_start(now - duration) - self.segment_millis()
+        };
+
+        let mut state = self.state.lock().unwrap();
+
+        // retention
+        if let Some(retention) = self.config.retention {
+            let last_expired = age(retention);
+            let expired: Vec<_> = state
+                .segments
+                .range(..=last_expired)
+                .map(|(&start, _)| start)
+                .collect();
+            for start in expired {
+                if state.writer.as_ref().map(|(current, _)| *current) == Some(start) {
+                    state.writer = None;
+                }
+                self.cache.lock().unwrap().remove(&start);
+                if let Some(segment) = state.segments.remove(&start) {
+                    remove_file(&segment.path)?;
+                }
+            }
+            self.latest.lock().unwrap().retain(|_, sample| {
+                sample.timestamp.timestamp_millis() >= last_expired + self.segment_millis()
+            });
+        }
+
+        // downsampling
+        if let Some(downsample_after) = self.config.downsample_after {
+            let targets: Vec<_> = state
+                .segments
+                .range(..=age(downsample_after))
+                .filter(|(_, segment)| !segment.downsampled)
+                .map(|(&start, segment)| (start, segment.clone()))
+                .collect();
+
+            for (start, segment) in targets {
+                if state.writer.as_ref().map(|(current, _)| *current) == Some(start) {
+                    state.writer = None;
+                }
+                self.cache.lock().unwrap().remove(&start);
+
+                let records = self.downsample(read_records(&segment.path)?);
+                let path = self
+                    .config
+                    .path
+                    .join(format!("{start}{EXTENSION_DOWNSAMPLED}"));
+                write_records(&path, &records)?;
+                remove_file(&segment.path)?;
+                state.segments.insert(
+                    start,
+                    Segment {
+                        path,
+                        downsampled: true,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn update_latest(
+    latest: &mut BTreeMap<DataRef, TimedLocation>,
+    data: DataRef,
+    location: TimedLocation,
+) {
+    match latest.get(&data) {
+        Some(last) if last.timestamp > location.timestamp => {}
+        _ => {
+            latest.insert(data, location);
+        }
+    }
+}
+
+fn read_records(path: &Path) -> Result<Vec<Record>> {
+    let mut records = Vec::default();
+    read_records_from(path, 0, &mut records)?;
+    Ok(records)
+}
+
+/// Parses the complete lines from the offset, returning the offset after them.
+fn read_records_from(path: &Path, offset: u64, records: &mut Vec<Record>) -> Result<u64> {
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut file = File::open(path).map_err(io_error)?;
+    file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+
+    let mut reader = BufReader::new(file);
+    let mut offset = offset;
+    let mut line = Vec::default();
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line).map_err(io_error)?;
+        // skip the partially written last line, which may be completed later
+        if len == 0 || line.last() != Some(&b'\n') {
+            return Ok(offset);
+        }
+        let start = offset;
+        offset += len as u64;
+
+        if line.len() == 1 {
+            continue;
+        }
+        match ::serde_json::from_slice(&line) {
+            Ok(record) => records.push(record),
+            Err(error) => eprintln!("skipping a broken record: {path:?}@{start}: {error}"),
+        }
+    }
+}
+
+/// Truncates the partially written last line, e.g. from a crash,
+/// not to append the next record onto it.
+fn repair(path: &Path) -> Result<()> {
+    let io_error = |source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(io_error(error)),
+    };
+
+    // find the end of the last complete line
+    let len = file.metadata().map_err(io_error)?.len();
+    let mut end = len;
+    let mut buf = [0; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(buf.len() as u64);
+        let chunk = &mut buf[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start)).map_err(io_error)?;
+        file.read_exact(chunk).map_err(io_error)?;
+        match chunk.iter().rposition(|&byte| byte == b'\n') {
+            Some(index) => {
+                end = start + index as u64 + 1;
+                break;
+            }
+            None => end = start,
+        }
+    }
+
+    if end < len {
+        eprintln!("truncating a partially written record: {path:?}");
+        file.set_len(end).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+fn write_records(path: &Path, records: &[Record]) -> Result<()> {
+    let path_tmp = path.with_extension("tmp");
+    let io_error = |source| Error::Io {
+        path: path_tmp.clone(),
+        source,
+    };
+
+    let mut writer = BufWriter::new(File::create(&path_tmp).map_err(io_error)?);
+    for record in records {
+        ::serde_json::to_writer(&mut writer, record)?;
+        writer.write_all(b"\n").map_err(io_error)?;
+    }
+    writer
+        .flush()
+        .and_then(|()| writer.get_ref().sync_data())
+        .map_err(io_error)?;
+    drop(writer);
+
+    fs::rename(&path_tmp, path).map_err(io_error)?;
+    sync_dir(path.parent().unwrap_or(path))
+}
+
+/// Fsyncs the directory, to persist the created and renamed files.
+fn sync_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(path)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    fs::remove_file(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use footprint_api::{GlobalLocation, LocalLocation, Motion};
+
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("footprint-segment-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+
+        fn files(&self) -> Vec<String> {
+            let mut files: Vec<_> = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn data(name: &str) -> DataRef {
+        DataRef {
+            kind: "user".into(),
+            name: name.into(),
+            namespace: None,
+        }
+    }
+
+    /// Seconds since 2000-01-01, old enough to be downsampled or expired.
+    fn at(sec: i64, latitude: f64) -> TimedLocation {
+        TimedLocation {
+            timestamp: DateTime::from_timestamp(946_684_800 + sec, 0).unwrap(),
+            location: Location {
+                global: GlobalLocation {
+                    error_m: 1.0,
+                    latitude,
+                    longitude: 127.0,
+                },
+                local: LocalLocation::default(),
+                motion: Motion::default(),
+            },
+        }
+    }
+
+    fn config(dir: &TempDir) -> SegmentStoreConfig {
+        SegmentStoreConfig {
+            segment: Duration::from_secs(60 * 60),
+            ..SegmentStoreConfig::new(&dir.0)
+        }
+    }
+
+    async fn records(store: &SegmentStore) -> Vec<(DataRef, TimedLocation)> {
+        store
+            .records(
+                &DataRefFilter::default(),
+                DateTime::from_timestamp(0, 0).unwrap(),
+                Utc::now(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn persist_and_reload() {
+        let dir = TempDir::new("reload");
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        store.put(&data("b"), &at(20, 2.0)).await.unwrap();
+        // another segment
+        store.put(&data("a"), &at(3600, 3.0)).await.unwrap();
+        drop(store);
+
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        let location = store.get(&data("a")).await.unwrap().unwrap();
+        assert_eq!(location.global.latitude, 3.0);
+        assert_eq!(
+            store.list(&DataRefFilter::default()).await.unwrap().len(),
+            2
+        );
+        assert_eq!(
+            records(&store).await,
+            [
+                (data("a"), at(10, 1.0)),
+                (data("b"), at(20, 2.0)),
+                (data("a"), at(3600, 3.0)),
+            ],
+        );
+
+        let history = store
+            .history(
+                &data("a"),
+                at(0, 0.0).timestamp,
+                at(30, 0.0).timestamp,
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+        assert_eq!(history, [at(10, 1.0), at(20, 1.0), at(30, 1.0)]);
+
+        let locations = store
+            .list_timed_at(&DataRefFilter::default(), at(15, 0.0).timestamp)
+            .await
+            .unwrap();
+        assert_eq!(
+            locations.into_iter().collect::<Vec<_>>(),
+            [(data("a"), at(10, 1.0))],
+        );
+    }
+
+    #[tokio::test]
+    async fn truncate_partial_record() {
+        let dir = TempDir::new("partial");
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        drop(store);
+
+        // a crash while writing a record
+        let path = dir.0.join(&dir.files()[0]);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"kind":"user","na"#).unwrap();
+        drop(file);
+
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        store.put(&data("a"), &at(20, 2.0)).await.unwrap();
+        assert_eq!(
+            records(&store).await,
+            [(data("a"), at(10, 1.0)), (data("a"), at(20, 2.0))],
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn keep_non_finite_values() {
+        let dir = TempDir::new("nan");
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        let mut location = at(10, 1.0);
+        location.location.local = LocalLocation {
+            x: f64::NAN,
+            y: f64::NAN,
+            error_m: f64::NAN,
+        };
+        store.put(&data("a"), &location).await.unwrap();
+        drop(store);
+
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        let records = records(&store).await;
+        assert_eq!(records.len(), 1);
+        let local = records[0].1.location.local;
+        assert!(local.x.is_nan() && local.y.is_nan() && local.error_m.is_nan());
+    }
+
+    #[tokio::test]
+    async fn downsample_late_records() {
+        let dir = TempDir::new("downsample");
+        let config = SegmentStoreConfig {
+            downsample_after: Some(Duration::from_secs(60 * 60)),
+            downsample_step: Duration::from_secs(60),
+            ..config(&dir)
+        };
+        let store = SegmentStore::open(config).unwrap();
+
+        // the new segment is downsampled at once
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        assert_eq!(dir.files(), ["946684800000.downsampled.jsonl"]);
+
+        // and the late records are merged into the downsampled segment
+        store.put(&data("a"), &at(30, 3.0)).await.unwrap();
+        store.put(&data("a"), &at(20, 2.0)).await.unwrap();
+        store.put(&data("a"), &at(70, 4.0)).await.unwrap();
+        store.put(&data("b"), &at(40, 5.0)).await.unwrap();
+        assert_eq!(dir.files(), ["946684800000.downsampled.jsonl"]);
+        assert_eq!(
+            records(&store).await,
+            [
+                (data("a"), at(30, 3.0)),
+                (data("b"), at(40, 5.0)),
+                (data("a"), at(70, 4.0)),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn expire_old_segments() {
+        let dir = TempDir::new("retention");
+        let config = SegmentStoreConfig {
+            retention: Some(Duration::from_secs(60 * 60)),
+            ..config(&dir)
+        };
+        let store = SegmentStore::open(config).unwrap();
+
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        assert!(dir.files().is_empty());
+        assert!(store.get(&data("a")).await.unwrap().is_none());
+        assert!(records(&store).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_appended_records() {
+        let dir = TempDir::new("cache");
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        assert_eq!(records(&store).await, [(data("a"), at(10, 1.0))]);
+
+        // the cached segment is parsed from where it was left
+        store.put(&data("b"), &at(20, 2.0)).await.unwrap();
+        let path = dir.0.join(&dir.files()[0]);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"kind":"user","name":"c","#).unwrap();
+        assert_eq!(
+            records(&store).await,
+            [(data("a"), at(10, 1.0)), (data("b"), at(20, 2.0))],
+        );
+
+        // the partial line is parsed once completed
+        let mut line = ::serde_json::to_string(&at(30, 3.0)).unwrap();
+        line.replace_range(..1, "");
+        writeln!(file, "{line}").unwrap();
+        assert_eq!(
+            records(&store).await,
+            [
+                (data("a"), at(10, 1.0)),
+                (data("b"), at(20, 2.0)),
+                (data("c"), at(30, 3.0)),
+            ],
+        );
+
+        // only the latest segments are kept parsed
+        for hour in 1..=3 {
+            store.put(&data("a"), &at(hour * 3600, 4.0)).await.unwrap();
+        }
+        assert_eq!(records(&store).await.len(), 6);
+        let cache = store.inner.cache.lock().unwrap();
+        assert_eq!(
+            cache.keys().copied().collect::<Vec<_>>(),
+            [
+                946_684_800_000 + 2 * 3_600_000,
+                946_684_800_000 + 3 * 3_600_000
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_on_interval() {
+        let dir = TempDir::new("sync");
+        let store = SegmentStore::open(SegmentStoreConfig {
+            sync_interval: Duration::ZERO,
+            ..config(&dir)
+        })
+        .unwrap();
+        store.put(&data("a"), &at(10, 1.0)).await.unwrap();
+        assert!(store.inner.state.lock().unwrap().unsynced_since.is_none());
+
+        let store = SegmentStore::open(config(&dir)).unwrap();
+        store.put(&data("a"), &at(20, 2.0)).await.unwrap();
+        assert!(store.inner.state.lock().unwrap().unsynced_since.is_some());
+        store.sync().await.unwrap();
+        assert!(store.inner.state.lock().unwrap().unsynced_since.is_none());
+    }
+}
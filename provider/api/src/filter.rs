@@ -0,0 +1,404 @@
+use std::{collections::HashMap, f64, sync::Mutex, time::Instant};
+
+use footprint_api::{GlobalLocation, Location, Motion, ObjectLocation, EARTH_RADIUS_M};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KalmanConfig {
+    /// Spectral density of the (white noise) acceleration, as `m^2/s^3`
+    pub acceleration_noise: f64,
+    /// Measurement error used when a sample does not provide its own `error_m`
+    pub error_m: f64,
+    /// Squared Mahalanobis distance above which a sample is rejected as an outlier
+    pub gate: f64,
+    /// Initial velocity uncertainty of a new object, as `m/s`
+    pub initial_speed_error_mps: f64,
+    /// Number of consecutive rejections after which the object is re-initialized
+    pub max_rejections: usize,
+}
+
+impl Default for KalmanConfig {
+    fn default() -> Self {
+        Self {
+            acceleration_noise: 1.0,
+            error_m: 1.0,
+            // chi-square with 2 degrees of freedom, p = 0.99
+            gate: 9.21,
+            initial_speed_error_mps: 10.0,
+            max_rejections: 5,
+        }
+    }
+}
+
+#[cfg(feature = "env")]
+impl KalmanConfig {
+    pub fn try_from_env() -> ::anyhow::Result<Self> {
+        use crate::env::env_var_opt;
+
+        let default = Self::default();
+        Ok(Self {
+            acceleration_noise: env_var_opt("FOOTPRINT_FILTER_ACCELERATION_NOISE")?
+                .unwrap_or(default.acceleration_noise),
+            error_m: env_var_opt("FOOTPRINT_FILTER_ERROR_M")?.unwrap_or(default.error_m),
+            gate: env_var_opt("FOOTPRINT_FILTER_GATE")?.unwrap_or(default.gate),
+            initial_speed_error_mps: env_var_opt("FOOTPRINT_FILTER_INITIAL_SPEED_ERROR_MPS")?
+                .unwrap_or(default.initial_speed_error_mps),
+            max_rejections: env_var_opt("FOOTPRINT_FILTER_MAX_REJECTIONS")?
+                .unwrap_or(default.max_rejections),
+        })
+    }
+}
+
+/// Smoothed location and motion of an object.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub location: ObjectLocation,
+    /// Whether the latest sample has been rejected by the gate
+    pub rejected: bool,
+}
+
+/// A constant-velocity Kalman filter, tracking each object independently.
+#[derive(Debug, Default)]
+pub struct KalmanFilter {
+    config: KalmanConfig,
+    objects: Mutex<HashMap<usize, ObjectState>>,
+}
+
+impl KalmanFilter {
+    pub fn new(config: KalmanConfig) -> Self {
+        Self {
+            config,
+            objects: Mutex::default(),
+        }
+    }
+
+    /// Returns a filter if it is enabled by `FOOTPRINT_FILTER`.
+    #[cfg(feature = "env")]
+    pub fn try_from_env() -> ::anyhow::Result<Option<Self>> {
+        match crate::env::env_var_opt::<String>("FOOTPRINT_FILTER")?.as_deref() {
+            None | Some("" | "none") => Ok(None),
+            Some("kalman") => KalmanConfig::try_from_env().map(Self::new).map(Some),
+            Some(filter) => ::anyhow::bail!("unsupported filter: {filter}"),
+        }
+    }
+
+    pub fn config(&self) -> &KalmanConfig {
+        &self.config
+    }
+
+    pub fn update(&self, location: ObjectLocation) -> Estimate {
+        self.update_at(location, Instant::now())
+    }
+
+    pub fn update_at(&self, location: ObjectLocation, now: Instant) -> Estimate {
+        let mut objects = self.objects.lock().unwrap();
+        let state = objects
+            .entry(location.id)
+            .or_insert_with(|| ObjectState::new(&self.config, &location.location, now));
+        state.update(&self.config, location, now)
+    }
+
+    pub fn remove(&self, id: usize) {
+        self.objects.lock().unwrap().remove(&id);
+    }
+}
+
+#[derive(Debug)]
+struct ObjectState {
+    origin: GlobalLocation,
+    east: AxisState,
+    north: AxisState,
+    rejections: usize,
+    timestamp: Instant,
+}
+
+impl ObjectState {
+    fn new(config: &KalmanConfig, location: &Location, timestamp: Instant) -> Self {
+        let variance = measurement_variance(config, location);
+        let speed_variance = config.initial_speed_error_mps.powi(2);
+        Self {
+            origin: location.global,
+            east: AxisState::new(0.0, variance, speed_variance),
+            north: AxisState::new(0.0, variance, speed_variance),
+            rejections: 0,
+            timestamp,
+        }
+    }
+
+    fn update(&mut self, config: &KalmanConfig, sample: ObjectLocation, now: Instant) -> Estimate {
+        // predict
+        let dt = now.saturating_duration_since(self.timestamp).as_secs_f64();
+        if dt > 0.0 {
+            self.east.predict(dt, config.acceleration_noise);
+            self.north.predict(dt, config.acceleration_noise);
+            self.timestamp = now;
+        }
+
+        // gate
+        let variance = measurement_variance(config, &sample.location);
+        let (east, north) = self.project(&sample.location.global);
+        let (east, east_variance) = self.east.innovation(east, variance);
+        let (north, north_variance) = self.north.innovation(north, variance);
+        let distance = east.powi(2) / east_variance + north.powi(2) / north_variance;
+
+        let rejected = distance > config.gate;
+        if rejected {
+            self.rejections += 1;
+            if self.rejections > config.max_rejections {
+                // the object has (probably) really moved; start over
+                *self = Self::new(config, &sample.location, now);
+                return self.estimate(sample, false);
+            }
+        } else {
+            self.rejections = 0;
+            self.east.correct(east, east_variance);
+            self.north.correct(north, north_variance);
+        }
+        self.estimate(sample, rejected)
+    }
+
+    fn estimate(&self, sample: ObjectLocation, rejected: bool) -> Estimate {
+        let (latitude, longitude) = self.unproject(self.east.position, self.north.position);
+        let error_m = (self.east.covariance[0][0] + self.north.covariance[0][0]).sqrt();
+
+        Estimate {
+            location: ObjectLocation {
+                id: sample.id,
+                location: Location {
+                    global: GlobalLocation {
+                        error_m,
+                        latitude,
+                        longitude,
+                    },
+                    local: sample.location.local,
+                    motion: Motion::from_velocity(self.east.velocity, self.north.velocity),
+                },
+            },
+            rejected,
+        }
+    }
+
+    fn meters_per_degree(&self) -> (f64, f64) {
+        let north = EARTH_RADIUS_M * f64::consts::PI / 180.0;
+        let east = north * self.origin.latitude.to_radians().cos();
+        (east, north)
+    }
+
+    fn project(&self, location: &GlobalLocation) -> (f64, f64) {
+        self.origin.offset_m(location)
+    }
+
+    fn unproject(&self, east_m: f64, north_m: f64) -> (f64, f64) {
+        let (east, north) = self.meters_per_degree();
+        (
+            self.origin.latitude + north_m / north,
+            self.origin.longitude + east_m / east,
+        )
+    }
+}
+
+/// The state of a single axis; the constant-velocity model keeps the axes independent.
+#[derive(Debug)]
+struct AxisState {
+    position: f64,
+    velocity: f64,
+    covariance: [[f64; 2]; 2],
+}
+
+impl AxisState {
+    fn new(position: f64, variance: f64, speed_variance: f64) -> Self {
+        Self {
+            position,
+            velocity: 0.0,
+            covariance: [[variance, 0.0], [0.0, speed_variance]],
+        }
+    }
+
+    fn predict(&mut self, dt: f64, acceleration_noise: f64) {
+        let [[p00, p01], [_, p11]] = self.covariance;
+        let q = acceleration_noise;
+
+        self.position += self.velocity * dt;
+
+        let p00 = p00 + 2.0 * dt * p01 + dt * dt * p11 + q * dt.powi(3) / 3.0;
+        let p01 = p01 + dt * p11 + q * dt.powi(2) / 2.0;
+        let p11 = p11 + q * dt;
+        self.covariance = [[p00, p01], [p01, p11]];
+    }
+
+    fn innovation(&self, measurement: f64, variance: f64) -> (f64, f64) {
+        (
+            measurement - self.position,
+            self.covariance[0][0] + variance,
+        )
+    }
+
+    fn correct(&mut self, innovation: f64, innovation_variance: f64) {
+        let [[p00, p01], [_, p11]] = self.covariance;
+        let k0 = p00 / innovation_variance;
+        let k1 = p01 / innovation_variance;
+
+        self.position += k0 * innovation;
+        self.velocity += k1 * innovation;
+
+        let p00 = (1.0 - k0) * p00;
+        let p11 = p11 - k1 * p01;
+        let p01 = (1.0 - k0) * p01;
+        self.covariance = [[p00, p01], [p01, p11]];
+    }
+}
+
+fn measurement_variance(config: &KalmanConfig, location: &Location) -> f64 {
+    let error_m = if location.global.error_m > 0.0 {
+        location.global.error_m
+    } else {
+        config.error_m
+    };
+    error_m.powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use footprint_api::LocalLocation;
+
+    use super::*;
+
+    const ORIGIN: GlobalLocation = GlobalLocation {
+        error_m: 1.0,
+        latitude: 37.5,
+        longitude: 127.0,
+    };
+
+    fn sample(east_m: f64, north_m: f64) -> ObjectLocation {
+        let north = EARTH_RADIUS_M * f64::consts::PI / 180.0;
+        let east = north * ORIGIN.latitude.to_radians().cos();
+        ObjectLocation {
+            id: 0,
+            location: Location {
+                global: GlobalLocation {
+                    latitude: ORIGIN.latitude + north_m / north,
+                    longitude: ORIGIN.longitude + east_m / east,
+                    ..ORIGIN
+                },
+                local: LocalLocation::default(),
+                motion: Motion::default(),
+            },
+        }
+    }
+
+    fn offset_m(estimate: &Estimate) -> (f64, f64) {
+        ORIGIN.offset_m(&estimate.location.location.global)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, but given {actual}",
+        );
+    }
+
+    #[test]
+    fn predict_covariance() {
+        let mut axis = AxisState::new(1.0, 1.0, 4.0);
+        axis.velocity = 3.0;
+        axis.predict(2.0, 0.5);
+
+        assert_close(axis.position, 7.0);
+        let [[p00, p01], [p10, p11]] = axis.covariance;
+        assert_close(p00, 1.0 + 4.0 * 4.0 + 0.5 * 8.0 / 3.0);
+        assert_close(p01, 2.0 * 4.0 + 0.5 * 4.0 / 2.0);
+        assert_close(p10, p01);
+        assert_close(p11, 4.0 + 0.5 * 2.0);
+    }
+
+    #[test]
+    fn correct_covariance() {
+        let mut axis = AxisState::new(0.0, 4.0, 3.0);
+        axis.covariance = [[4.0, 2.0], [2.0, 3.0]];
+        let (innovation, variance) = axis.innovation(2.0, 1.0);
+        assert_close(innovation, 2.0);
+        assert_close(variance, 5.0);
+
+        axis.correct(innovation, variance);
+        assert_close(axis.position, 0.8 * 2.0);
+        assert_close(axis.velocity, 0.4 * 2.0);
+        let [[p00, p01], [p10, p11]] = axis.covariance;
+        assert_close(p00, 0.2 * 4.0);
+        assert_close(p01, 0.2 * 2.0);
+        assert_close(p10, p01);
+        assert_close(p11, 3.0 - 0.4 * 2.0);
+    }
+
+    #[test]
+    fn track_constant_velocity() {
+        let filter = KalmanFilter::default();
+        let start = Instant::now();
+
+        let mut estimate = None;
+        for step in 0..30 {
+            let now = start + Duration::from_secs(step);
+            estimate = Some(filter.update_at(sample(2.0 * step as f64, 0.0), now));
+        }
+
+        let estimate = estimate.unwrap();
+        assert!(!estimate.rejected);
+        let (east, north) = offset_m(&estimate);
+        assert!((east - 58.0).abs() < 1.0, "east: {east}");
+        assert!(north.abs() < 1.0, "north: {north}");
+
+        let motion = estimate.location.location.motion;
+        let speed = motion.speed_mps.unwrap();
+        let heading = motion.heading_deg.unwrap();
+        assert!((speed - 2.0).abs() < 0.1, "speed: {speed}");
+        assert!((heading - 90.0).abs() < 1.0, "heading: {heading}");
+    }
+
+    #[test]
+    fn gate_outliers_and_reinitialize() {
+        let config = KalmanConfig {
+            max_rejections: 3,
+            ..Default::default()
+        };
+        let filter = KalmanFilter::new(config);
+        let start = Instant::now();
+        let at = |step: u64| start + Duration::from_secs(step);
+
+        for step in 0..10 {
+            assert!(!filter.update_at(sample(0.0, 0.0), at(step)).rejected);
+        }
+
+        // a single outlier is rejected without moving the estimate
+        let estimate = filter.update_at(sample(1000.0, 0.0), at(10));
+        assert!(estimate.rejected);
+        assert!(offset_m(&estimate).0.abs() < 1.0);
+
+        // and an accepted sample resets the rejections
+        assert!(!filter.update_at(sample(0.0, 0.0), at(11)).rejected);
+
+        // the object is re-initialized after too many consecutive rejections
+        for step in 12..15 {
+            assert!(filter.update_at(sample(1000.0, 0.0), at(step)).rejected);
+        }
+        let estimate = filter.update_at(sample(1000.0, 0.0), at(15));
+        assert!(!estimate.rejected);
+        let (east, _) = offset_m(&estimate);
+        assert!((east - 1000.0).abs() < 1e-3, "east: {east}");
+        assert_close(estimate.location.location.global.error_m, 2.0_f64.sqrt());
+    }
+
+    #[test]
+    fn track_objects_independently() {
+        let filter = KalmanFilter::default();
+        let now = Instant::now();
+
+        filter.update_at(sample(0.0, 0.0), now);
+        let other = ObjectLocation {
+            id: 1,
+            ..sample(1000.0, 0.0)
+        };
+        let estimate = filter.update_at(other, now + Duration::from_secs(1));
+        assert!(!estimate.rejected);
+        assert!((offset_m(&estimate).0 - 1000.0).abs() < 1e-3);
+    }
+}
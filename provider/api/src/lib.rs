@@ -19,10 +19,14 @@ pub mod env {
         pub fn new() -> Result<Self> {
             let tick_sec = env_var("FOOTPRINT_TICK_SEC")?;
             Ok(Self {
-                interval: Duration::from_secs_f64(tick_sec),
+                interval: secs("FOOTPRINT_TICK_SEC", tick_sec)?,
             })
         }
 
+        pub fn interval(&self) -> Duration {
+            self.interval
+        }
+
         pub fn spawn<F>(self, mut f: F)
         where
             F: 'static + Send + FnMut() -> Result<()>,
@@ -69,16 +73,54 @@ pub mod env {
                 })
             })
     }
+
+    pub fn env_var_opt<T>(key: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        <T as FromStr>::Err: Error,
+    {
+        match env::var(key) {
+            Ok(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|error| anyhow!("failed to parse environment variable: {key}: {error}")),
+            Err(VarError::NotPresent) => Ok(None),
+            Err(error) => Err(anyhow!(
+                "failed to get environment variable: {key}: {error}"
+            )),
+        }
+    }
+
+    /// Returns the seconds given by the environment variable as a duration, or `None` if not given.
+    pub fn env_secs_opt(key: &str) -> Result<Option<Duration>> {
+        env_var_opt(key)?.map(|value| secs(key, value)).transpose()
+    }
+
+    fn secs(key: &str, value: f64) -> Result<Duration> {
+        Duration::try_from_secs_f64(value)
+            .map_err(|error| anyhow!("invalid environment variable: {key}: {value}: {error}"))
+    }
 }
 
+pub mod filter;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+#[cfg(feature = "remote-write")]
+pub mod remote_write;
+
 #[cfg(feature = "metrics")]
 pub fn register(registry: &::prometheus::Registry) -> ::prometheus::Result<()> {
     registry.register(Box::new(self::metrics::GAUGE_ERROR_M.clone()))?;
     registry.register(Box::new(self::metrics::GAUGE_LATITUDE.clone()))?;
     registry.register(Box::new(self::metrics::GAUGE_LONGITUDE.clone()))?;
+    registry.register(Box::new(self::metrics::GAUGE_VELOCITY_EAST_MPS.clone()))?;
+    registry.register(Box::new(self::metrics::GAUGE_VELOCITY_NORTH_MPS.clone()))?;
+    registry.register(Box::new(self::metrics::GAUGE_SPEED_MPS.clone()))?;
+    registry.register(Box::new(self::metrics::GAUGE_HEADING_DEG.clone()))?;
     Ok(())
 }
 
+/// Exports the location of the object, observed at the timestamp.
 #[cfg(feature = "metrics")]
 pub fn update(
     ::footprint_api::ObjectLocation {
@@ -92,22 +134,66 @@ pub fn update(
                         longitude,
                     },
                 local: _,
+                motion:
+                    ::footprint_api::Motion {
+                        velocity_east_mps,
+                        velocity_north_mps,
+                        speed_mps,
+                        heading_deg,
+                    },
             },
     }: ::footprint_api::ObjectLocation,
+    timestamp: ::chrono::DateTime<::chrono::Utc>,
 ) {
+    // unknown motions are exported as NaN
+    let velocity_east_mps = velocity_east_mps.unwrap_or(f64::NAN);
+    let velocity_north_mps = velocity_north_mps.unwrap_or(f64::NAN);
+    let speed_mps = speed_mps.unwrap_or(f64::NAN);
+    let heading_deg = heading_deg.unwrap_or(f64::NAN);
+
     self::metrics::GAUGE_ERROR_M.set(error_m);
     self::metrics::GAUGE_LATITUDE.set(latitude);
     self::metrics::GAUGE_LONGITUDE.set(longitude);
+    self::metrics::GAUGE_VELOCITY_EAST_MPS.set(velocity_east_mps);
+    self::metrics::GAUGE_VELOCITY_NORTH_MPS.set(velocity_north_mps);
+    self::metrics::GAUGE_SPEED_MPS.set(speed_mps);
+    self::metrics::GAUGE_HEADING_DEG.set(heading_deg);
+
+    #[cfg(not(feature = "remote-write"))]
+    let _ = timestamp;
+    #[cfg(feature = "remote-write")]
+    self::remote_write::push(
+        timestamp,
+        &[
+            (self::consts::METRIC_ERROR_M, error_m),
+            (self::consts::METRIC_LATITUDE, latitude),
+            (self::consts::METRIC_LONGITUDE, longitude),
+            (self::consts::METRIC_VELOCITY_EAST_MPS, velocity_east_mps),
+            (self::consts::METRIC_VELOCITY_NORTH_MPS, velocity_north_mps),
+            (self::consts::METRIC_SPEED_MPS, speed_mps),
+            (self::consts::METRIC_HEADING_DEG, heading_deg),
+        ],
+    );
 }
 
 pub mod consts {
     pub const METRIC_ERROR_M: &str = "ulagbulag_footprint_error_m";
     pub const METRIC_LATITUDE: &str = "ulagbulag_footprint_latitude";
     pub const METRIC_LONGITUDE: &str = "ulagbulag_footprint_longitude";
+    pub const METRIC_VELOCITY_EAST_MPS: &str = "ulagbulag_footprint_velocity_east_mps";
+    pub const METRIC_VELOCITY_NORTH_MPS: &str = "ulagbulag_footprint_velocity_north_mps";
+    pub const METRIC_SPEED_MPS: &str = "ulagbulag_footprint_speed_mps";
+    pub const METRIC_HEADING_DEG: &str = "ulagbulag_footprint_heading_deg";
+    pub const METRIC_GEOFENCE_MEMBER: &str = "ulagbulag_footprint_geofence_member";
 
     pub const LABEL_KIND: &str = "footprint_kind";
     pub const LABEL_NAME: &str = "footprint_name";
     pub const LABEL_NAMESPACE: &str = "footprint_namespace";
+    pub const LABEL_GEOFENCE: &str = "footprint_geofence";
+
+    pub const ATTRIBUTE_KIND: &str = "footprint.kind";
+    pub const ATTRIBUTE_NAME: &str = "footprint.name";
+    pub const ATTRIBUTE_NAMESPACE: &str = "footprint.namespace";
 }
 
 #[cfg(feature = "metrics")]
@@ -138,6 +224,26 @@ mod metrics {
             super::consts::METRIC_LONGITUDE,
             "Geolocational Data: Longitude",
         );
+
+        pub(crate) static ref GAUGE_VELOCITY_EAST_MPS: GenericGauge<AtomicF64> = new_gauge(
+            super::consts::METRIC_VELOCITY_EAST_MPS,
+            "Geolocational Data: Eastward Velocity as Meter per Second",
+        );
+
+        pub(crate) static ref GAUGE_VELOCITY_NORTH_MPS: GenericGauge<AtomicF64> = new_gauge(
+            super::consts::METRIC_VELOCITY_NORTH_MPS,
+            "Geolocational Data: Northward Velocity as Meter per Second",
+        );
+
+        pub(crate) static ref GAUGE_SPEED_MPS: GenericGauge<AtomicF64> = new_gauge(
+            super::consts::METRIC_SPEED_MPS,
+            "Geolocational Data: Speed as Meter per Second",
+        );
+
+        pub(crate) static ref GAUGE_HEADING_DEG: GenericGauge<AtomicF64> = new_gauge(
+            super::consts::METRIC_HEADING_DEG,
+            "Geolocational Data: Heading as Degree (clockwise from the north)",
+        );
     }
 
     fn get_env_var(key: &str) -> String {
@@ -0,0 +1,128 @@
+//! Exports the location samples as OpenTelemetry gauges to an OTLP collector.
+
+use std::{sync::OnceLock, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use opentelemetry::{
+    metrics::{MeterProvider as _, ObservableGauge},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{metrics::MeterProvider, runtime, Resource};
+use prometheus::core::{AtomicF64, Collector, GenericGauge};
+
+use crate::env::{env_secs_opt, env_var, env_var_opt};
+
+static PROVIDER: OnceLock<(MeterProvider, Vec<ObservableGauge<f64>>)> = OnceLock::new();
+
+#[derive(Clone, Debug)]
+pub struct OtlpConfig {
+    /// gRPC endpoint of the collector, e.g. `http://otel-collector:4317`
+    pub endpoint: String,
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl OtlpConfig {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Returns `None` if `FOOTPRINT_OTLP_ENDPOINT` is not given.
+    pub fn try_from_env() -> Result<Option<Self>> {
+        let endpoint: String = match env_var_opt("FOOTPRINT_OTLP_ENDPOINT")? {
+            Some(endpoint) => endpoint,
+            None => return Ok(None),
+        };
+        let mut config = Self::new(endpoint);
+
+        if let Some(interval) = env_secs_opt("FOOTPRINT_OTLP_INTERVAL_SEC")? {
+            config.interval = interval;
+        }
+        if let Some(timeout) = env_secs_opt("FOOTPRINT_OTLP_TIMEOUT_SEC")? {
+            config.timeout = timeout;
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Starts the exporter if `FOOTPRINT_OTLP_ENDPOINT` is given.
+pub fn try_spawn_from_env() -> Result<bool> {
+    match OtlpConfig::try_from_env()? {
+        Some(config) => spawn(config).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Periodically exports the latest samples, which should be called within a tokio runtime.
+pub fn spawn(config: OtlpConfig) -> Result<()> {
+    if config.interval.is_zero() || config.timeout.is_zero() {
+        bail!("OTLP interval and timeout should be positive");
+    }
+
+    let resource = Resource::new([
+        KeyValue::new("service.name", "footprint"),
+        KeyValue::new(
+            crate::consts::ATTRIBUTE_KIND,
+            env_var::<String>("FOOTPRINT_KIND")?,
+        ),
+        KeyValue::new(
+            crate::consts::ATTRIBUTE_NAME,
+            env_var::<String>("FOOTPRINT_NAME")?,
+        ),
+        KeyValue::new(
+            crate::consts::ATTRIBUTE_NAMESPACE,
+            env_var_opt::<String>("FOOTPRINT_NAMESPACE")?.unwrap_or_default(),
+        ),
+    ]);
+
+    let provider = ::opentelemetry_otlp::new_pipeline()
+        .metrics(runtime::TokioCurrentThread)
+        .with_exporter(
+            ::opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(config.endpoint)
+                .with_timeout(config.timeout),
+        )
+        .with_resource(resource)
+        .with_period(config.interval)
+        .with_timeout(config.timeout)
+        .build()?;
+
+    let meter = provider.meter("footprint");
+    let gauges = [
+        &*crate::metrics::GAUGE_ERROR_M,
+        &*crate::metrics::GAUGE_LATITUDE,
+        &*crate::metrics::GAUGE_LONGITUDE,
+        &*crate::metrics::GAUGE_VELOCITY_EAST_MPS,
+        &*crate::metrics::GAUGE_VELOCITY_NORTH_MPS,
+        &*crate::metrics::GAUGE_SPEED_MPS,
+        &*crate::metrics::GAUGE_HEADING_DEG,
+    ]
+    .into_iter()
+    .map(|gauge: &GenericGauge<AtomicF64>| {
+        let desc = gauge.desc()[0];
+        let gauge = gauge.clone();
+        meter
+            .f64_observable_gauge(desc.fq_name.clone())
+            .with_description(desc.help.clone())
+            .with_callback(move |observer| {
+                // skip the unknown motions
+                let value = gauge.get();
+                if !value.is_nan() {
+                    observer.observe(value, &[]);
+                }
+            })
+            .init()
+    })
+    .collect();
+
+    // keep the instruments alive until the process is terminated
+    PROVIDER
+        .set((provider, gauges))
+        .map_err(|_| anyhow!("OTLP exporter is already running"))
+}
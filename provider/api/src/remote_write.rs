@@ -0,0 +1,291 @@
+//! Pushes every updated sample to a Prometheus remote-write endpoint, so that no update is
+//! lost between scrapes and each sample keeps its own observation timestamp.
+
+use std::{collections::BTreeMap, sync::OnceLock, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use prost::Message;
+use reqwest::{header, Client, StatusCode, Url};
+use tokio::{
+    sync::mpsc,
+    time::{sleep, timeout_at, Instant},
+};
+
+use crate::env::{env_secs_opt, env_var, env_var_opt};
+
+static SENDER: OnceLock<mpsc::Sender<Sample>> = OnceLock::new();
+
+#[derive(Clone, Debug)]
+pub struct RemoteWriteConfig {
+    pub url: Url,
+    /// Maximum number of samples in a single request
+    pub batch_size: usize,
+    /// Maximum time to wait for a batch to be filled
+    pub flush_interval: Duration,
+    /// Maximum number of samples waiting to be sent
+    pub queue_capacity: usize,
+    /// Maximum number of retries of a failed request; retries forever if not given
+    pub max_retries: Option<u32>,
+    pub timeout: Duration,
+}
+
+impl RemoteWriteConfig {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+            queue_capacity: 100_000,
+            max_retries: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Returns `None` if `FOOTPRINT_REMOTE_WRITE_URL` is not given.
+    pub fn try_from_env() -> Result<Option<Self>> {
+        let url = match env_var_opt("FOOTPRINT_REMOTE_WRITE_URL")? {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let mut config = Self::new(url);
+
+        if let Some(batch_size) = env_var_opt("FOOTPRINT_REMOTE_WRITE_BATCH_SIZE")? {
+            config.batch_size = batch_size;
+        }
+        if let Some(flush_interval) = env_secs_opt("FOOTPRINT_REMOTE_WRITE_FLUSH_SEC")? {
+            config.flush_interval = flush_interval;
+        }
+        if let Some(queue_capacity) = env_var_opt("FOOTPRINT_REMOTE_WRITE_QUEUE_CAPACITY")? {
+            config.queue_capacity = queue_capacity;
+        }
+        config.max_retries = env_var_opt("FOOTPRINT_REMOTE_WRITE_MAX_RETRIES")?;
+        if let Some(timeout) = env_secs_opt("FOOTPRINT_REMOTE_WRITE_TIMEOUT_SEC")? {
+            config.timeout = timeout;
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Starts the exporter if `FOOTPRINT_REMOTE_WRITE_URL` is given.
+pub fn try_spawn_from_env() -> Result<bool> {
+    match RemoteWriteConfig::try_from_env()? {
+        Some(config) => spawn(config).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+pub fn spawn(config: RemoteWriteConfig) -> Result<()> {
+    if config.batch_size == 0 || config.queue_capacity == 0 {
+        bail!("remote-write batch size and queue capacity should be positive");
+    }
+    if config.timeout.is_zero() {
+        bail!("remote-write timeout should be positive");
+    }
+
+    let client = Client::builder()
+        .timeout(config.timeout)
+        .use_rustls_tls()
+        .build()?;
+    let labels = Labels::try_from_env()?;
+
+    let (tx, rx) = mpsc::channel(config.queue_capacity);
+    SENDER
+        .set(tx)
+        .map_err(|_| anyhow!("remote-write exporter is already running"))?;
+
+    ::tokio::task::spawn(run(client, config, labels, rx));
+    Ok(())
+}
+
+/// Queues the samples observed at the timestamp, if the exporter is running.
+pub(crate) fn push(timestamp: DateTime<Utc>, values: &[(&'static str, f64)]) {
+    let sender = match SENDER.get() {
+        Some(sender) => sender,
+        None => return,
+    };
+
+    let timestamp = timestamp.timestamp_millis();
+    for &(metric, value) in values {
+        let sample = Sample {
+            metric,
+            value,
+            timestamp,
+        };
+        if sender.try_send(sample).is_err() {
+            eprintln!("remote-write queue is full; dropping a sample of {metric}");
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Sample {
+    metric: &'static str,
+    value: f64,
+    timestamp: i64,
+}
+
+struct Labels {
+    kind: String,
+    name: String,
+    namespace: String,
+}
+
+impl Labels {
+    fn try_from_env() -> Result<Self> {
+        Ok(Self {
+            kind: env_var("FOOTPRINT_KIND")?,
+            name: env_var("FOOTPRINT_NAME")?,
+            namespace: env_var_opt("FOOTPRINT_NAMESPACE")?.unwrap_or_default(),
+        })
+    }
+
+    fn to_proto(&self, metric: &str) -> Vec<proto::Label> {
+        // the labels should be sorted by their names
+        [
+            ("__name__", metric),
+            (crate::consts::LABEL_KIND, &self.kind),
+            (crate::consts::LABEL_NAME, &self.name),
+            (crate::consts::LABEL_NAMESPACE, &self.namespace),
+        ]
+        .into_iter()
+        .map(|(name, value)| proto::Label {
+            name: name.into(),
+            value: value.into(),
+        })
+        .collect()
+    }
+}
+
+async fn run(
+    client: Client,
+    config: RemoteWriteConfig,
+    labels: Labels,
+    mut rx: mpsc::Receiver<Sample>,
+) {
+    let mut batch = Vec::with_capacity(config.batch_size);
+    while let Some(sample) = rx.recv().await {
+        batch.push(sample);
+
+        let deadline = Instant::now() + config.flush_interval;
+        while batch.len() < config.batch_size {
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(sample)) => batch.push(sample),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let body = encode(&labels, &batch);
+        if let Err(error) = send(&client, &config, body).await {
+            eprintln!("failed to remote-write {} samples: {error}", batch.len());
+        }
+        batch.clear();
+    }
+}
+
+fn encode(labels: &Labels, batch: &[Sample]) -> Vec<u8> {
+    let mut series: BTreeMap<_, Vec<_>> = BTreeMap::default();
+    for sample in batch {
+        series
+            .entry(sample.metric)
+            .or_default()
+            .push(proto::Sample {
+                value: sample.value,
+                timestamp: sample.timestamp,
+            });
+    }
+
+    let request = proto::WriteRequest {
+        timeseries: series
+            .into_iter()
+            .map(|(metric, mut samples)| {
+                // the samples should be sorted by their timestamps
+                samples.sort_by_key(|sample| sample.timestamp);
+                proto::TimeSeries {
+                    labels: labels.to_proto(metric),
+                    samples,
+                }
+            })
+            .collect(),
+    };
+    ::snap::raw::Encoder::new()
+        .compress_vec(&request.encode_to_vec())
+        .expect("snappy compression of an in-memory buffer should not fail")
+}
+
+async fn send(client: &Client, config: &RemoteWriteConfig, body: Vec<u8>) -> Result<()> {
+    let mut backoff = Duration::from_millis(100);
+    let mut attempt = 0;
+    loop {
+        let response = client
+            .post(config.url.clone())
+            .header(header::CONTENT_ENCODING, "snappy")
+            .header(header::CONTENT_TYPE, "application/x-protobuf")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(body.clone())
+            .send()
+            .await;
+
+        let error = match response {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let error = anyhow!("unexpected HTTP status {status}: {body}");
+
+                // the other client errors would never succeed
+                if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                    return Err(error);
+                }
+                error
+            }
+            Err(error) => error.into(),
+        };
+
+        if config
+            .max_retries
+            .is_some_and(|max_retries| attempt >= max_retries)
+        {
+            return Err(error);
+        }
+        eprintln!("failed to remote-write; retrying in {backoff:?}: {error}");
+        sleep(backoff).await;
+
+        attempt += 1;
+        backoff = (backoff * 2).min(Duration::from_secs(30));
+    }
+}
+
+/// A subset of the Prometheus remote-write protocol (`prometheus.WriteRequest`).
+mod proto {
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub(super) struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub(super) struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub(super) labels: Vec<Label>,
+        #[prost(message, repeated, tag = "2")]
+        pub(super) samples: Vec<Sample>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub(super) struct Label {
+        #[prost(string, tag = "1")]
+        pub(super) name: String,
+        #[prost(string, tag = "2")]
+        pub(super) value: String,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub(super) struct Sample {
+        #[prost(double, tag = "1")]
+        pub(super) value: f64,
+        /// Milliseconds since the UNIX epoch
+        #[prost(int64, tag = "2")]
+        pub(super) timestamp: i64,
+    }
+}
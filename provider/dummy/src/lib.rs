@@ -1,51 +1,202 @@
-use anyhow::Result;
-use footprint_api::{GlobalLocation, LocalLocation, Location, ObjectLocation};
-use footprint_provider_api::env::{env_var, Tick};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Utc;
+use footprint_api::{GlobalLocation, LocalLocation, Location, Motion, ObjectLocation};
+use footprint_provider_api::{
+    env::{env_var, env_var_opt, Tick},
+    filter::KalmanFilter,
+};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use rand_distr::Normal;
 use tokio::sync::Mutex;
 
 pub async fn spawn() -> Result<()> {
-    let metrics = ::std::sync::Arc::new(Metrics::new().await?);
     let tick = Tick::new()?;
+    let metrics = ::std::sync::Arc::new(Metrics::new(tick.interval()).await?);
+    let filter = ::std::sync::Arc::new(KalmanFilter::try_from_env()?);
 
     tick.spawn_async(move || {
         let metrics = metrics.clone();
-        async move { metrics.next().await.map(::footprint_provider_api::update) }
+        let filter = filter.clone();
+        async move {
+            let location = metrics.next().await?;
+            let timestamp = Utc::now();
+            let location = match filter.as_ref() {
+                Some(filter) => filter.update(location).location,
+                None => location,
+            };
+            ::footprint_provider_api::update(location, timestamp);
+            Ok(())
+        }
     });
     Ok(())
 }
 
 struct Metrics {
     error_m: Mutex<Metric>,
-    latitude: Mutex<Metric>,
-    longitude: Mutex<Metric>,
+    interval: Duration,
+    position: Mutex<Position>,
+}
+
+enum Position {
+    /// Random walks around the base
+    RandomWalk { latitude: Metric, longitude: Metric },
+    /// Moves along the waypoints at a constant speed
+    Waypoints(Route),
 }
 
 impl Metrics {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(interval: Duration) -> Result<Self> {
+        let position = match env_var_opt::<String>("FOOTPRINT_WAYPOINTS")? {
+            Some(waypoints) => Position::Waypoints(Route::new(
+                parse_waypoints(&waypoints)?,
+                env_var("FOOTPRINT_WAYPOINTS_SPEED_MPS")?,
+            )?),
+            None => Position::RandomWalk {
+                latitude: Metric::new("LATITUDE", false)?,
+                longitude: Metric::new("LONGITUDE", false)?,
+            },
+        };
+
         Ok(Self {
             error_m: Mutex::new(Metric::new("ERROR_M", true)?),
-            latitude: Mutex::new(Metric::new("LATITUDE", false)?),
-            longitude: Mutex::new(Metric::new("LONGITUDE", false)?),
+            interval,
+            position: Mutex::new(position),
         })
     }
 
     pub async fn next(&self) -> Result<ObjectLocation> {
+        let error_m = self.error_m.lock().await.next();
+        let interval = self.interval.as_secs_f64();
+
+        let (global, motion) = match &mut *self.position.lock().await {
+            Position::RandomWalk {
+                latitude,
+                longitude,
+            } => {
+                let last = GlobalLocation {
+                    error_m: 0.0,
+                    latitude: latitude.last,
+                    longitude: longitude.last,
+                };
+                let global = GlobalLocation {
+                    error_m,
+                    latitude: latitude.next(),
+                    longitude: longitude.next(),
+                };
+
+                let (east_m, north_m) = last.offset_m(&global);
+                let motion = if interval > 0.0 {
+                    Motion::from_velocity(east_m / interval, north_m / interval)
+                } else {
+                    Motion::default()
+                };
+                (global, motion)
+            }
+            Position::Waypoints(route) => {
+                let (global, motion) = route.next(interval);
+                (GlobalLocation { error_m, ..global }, motion)
+            }
+        };
+
         Ok(ObjectLocation {
             id: 0,
             location: Location {
-                global: GlobalLocation {
-                    error_m: self.error_m.lock().await.next(),
-                    latitude: self.latitude.lock().await.next(),
-                    longitude: self.longitude.lock().await.next(),
-                },
+                global,
                 local: LocalLocation::default(),
+                motion,
             },
         })
     }
 }
 
+/// Parses the waypoints, e.g. `35.2274,126.8403;35.2280,126.8410`.
+fn parse_waypoints(waypoints: &str) -> Result<Vec<GlobalLocation>> {
+    waypoints
+        .split(';')
+        .map(str::trim)
+        .filter(|waypoint| !waypoint.is_empty())
+        .map(|waypoint| {
+            let (latitude, longitude) = waypoint.split_once(',').ok_or_else(|| {
+                anyhow!("the waypoint should be `latitude,longitude`: {waypoint}")
+            })?;
+            Ok(GlobalLocation {
+                error_m: 0.0,
+                latitude: latitude
+                    .trim()
+                    .parse()
+                    .map_err(|error| anyhow!("invalid latitude: {waypoint}: {error}"))?,
+                longitude: longitude
+                    .trim()
+                    .parse()
+                    .map_err(|error| anyhow!("invalid longitude: {waypoint}: {error}"))?,
+            })
+        })
+        .collect()
+}
+
+/// A closed route visiting the waypoints in order, starting from the first one.
+struct Route {
+    waypoints: Vec<GlobalLocation>,
+    speed_mps: f64,
+
+    current: GlobalLocation,
+    target: usize,
+}
+
+impl Route {
+    fn new(waypoints: Vec<GlobalLocation>, speed_mps: f64) -> Result<Self> {
+        let current = *waypoints
+            .first()
+            .ok_or_else(|| anyhow!("empty waypoints"))?;
+        if !speed_mps.is_finite() || speed_mps < 0.0 {
+            bail!("invalid speed of the waypoints: {speed_mps}");
+        }
+
+        Ok(Self {
+            target: 1 % waypoints.len(),
+            waypoints,
+            speed_mps,
+            current,
+        })
+    }
+
+    /// Moves for `interval` seconds, returning the new location and its motion.
+    fn next(&mut self, interval: f64) -> (GlobalLocation, Motion) {
+        let mut remaining_m = self.speed_mps * interval.max(0.0);
+        let mut direction = None;
+
+        // visit at most every waypoint once per tick, not to spin on the duplicated ones
+        for _ in 0..self.waypoints.len() {
+            let target = self.waypoints[self.target];
+            let (east_m, north_m) = self.current.offset_m(&target);
+            let distance_m = east_m.hypot(north_m);
+            if distance_m > 0.0 {
+                direction = Some((east_m / distance_m, north_m / distance_m));
+            }
+
+            if distance_m > remaining_m {
+                let ratio = remaining_m / distance_m;
+                self.current.latitude += (target.latitude - self.current.latitude) * ratio;
+                self.current.longitude += (target.longitude - self.current.longitude) * ratio;
+                break;
+            }
+            remaining_m -= distance_m;
+            self.current = target;
+            self.target = (self.target + 1) % self.waypoints.len();
+        }
+
+        let motion = match direction {
+            Some((east, north)) => {
+                Motion::from_velocity(east * self.speed_mps, north * self.speed_mps)
+            }
+            None => Motion::from_velocity(0.0, 0.0),
+        };
+        (self.current, motion)
+    }
+}
+
 struct Metric {
     base: f64,
     dist: Normal<f64>,
@@ -83,3 +234,60 @@ impl Metric {
         now
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let waypoints = parse_waypoints(" 35.0,126.0; 35.001 ,126.001;").unwrap();
+        assert_eq!(waypoints.len(), 2);
+        assert_eq!(waypoints[1].latitude, 35.001);
+        assert_eq!(waypoints[1].longitude, 126.001);
+
+        assert!(parse_waypoints("35.0").is_err());
+        assert!(parse_waypoints("35.0,east").is_err());
+        assert!(Route::new(Vec::default(), 1.0).is_err());
+        assert!(Route::new(waypoints, -1.0).is_err());
+    }
+
+    #[test]
+    fn follow_waypoints() {
+        let origin = GlobalLocation {
+            error_m: 0.0,
+            latitude: 35.0,
+            longitude: 126.0,
+        };
+        let north = GlobalLocation {
+            latitude: origin.latitude + 0.001,
+            ..origin
+        };
+        let leg_m = origin.distance_m(&north);
+        let mut route = Route::new(vec![origin, north], 10.0).unwrap();
+
+        // heading to the north
+        let (location, motion) = route.next(1.0);
+        assert!((origin.distance_m(&location) - 10.0).abs() < 0.01);
+        assert!((motion.speed_mps.unwrap() - 10.0).abs() < 1e-9);
+        assert!(motion.heading_deg.unwrap().abs() < 1e-6);
+
+        // passing the last waypoint, and heading back to the first one
+        let (location, motion) = route.next(leg_m / 10.0);
+        assert!((north.distance_m(&location) - 10.0).abs() < 0.01);
+        assert!((motion.heading_deg.unwrap() - 180.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stay_on_single_waypoint() {
+        let origin = GlobalLocation {
+            error_m: 0.0,
+            latitude: 35.0,
+            longitude: 126.0,
+        };
+        let mut route = Route::new(vec![origin], 10.0).unwrap();
+        let (location, motion) = route.next(1.0);
+        assert_eq!(location, origin);
+        assert_eq!(motion.speed_mps, Some(0.0));
+    }
+}
@@ -1,6 +1,7 @@
 use std::f64;
 
 use anyhow::{bail, Error, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use footprint_api::{Base, GlobalLocation, LocalLocation, LocationVectorScale, ObjectLocation};
 use footprint_provider_api::env::env_var;
 use url::Url;
@@ -8,11 +9,22 @@ use url::Url;
 #[cfg(feature = "metrics")]
 pub async fn spawn() -> Result<()> {
     let metrics = ::std::sync::Arc::new(Metrics::new().await?);
+    let filter =
+        ::std::sync::Arc::new(::footprint_provider_api::filter::KalmanFilter::try_from_env()?);
     let tick = ::footprint_provider_api::env::Tick::new()?;
 
     tick.spawn_async(move || {
         let metrics = metrics.clone();
-        async move { metrics.next().await.map(::footprint_provider_api::update) }
+        let filter = filter.clone();
+        async move {
+            let (location, timestamp) = metrics.next().await?;
+            let location = match filter.as_ref() {
+                Some(filter) => filter.update(location).location,
+                None => location,
+            };
+            ::footprint_provider_api::update(location, timestamp);
+            Ok(())
+        }
     });
     Ok(())
 }
@@ -89,7 +101,8 @@ impl Metrics {
         })
     }
 
-    pub async fn next(&self) -> Result<ObjectLocation> {
+    /// Returns the next location, with the timestamp when it was observed.
+    pub async fn next(&self) -> Result<(ObjectLocation, DateTime<Utc>)> {
         match &self.client {
             #[cfg(feature = "metrics")]
             Client::Metrics(client) => {
@@ -103,7 +116,10 @@ impl Metrics {
                     .await?;
 
                 let local_location = LocalLocation::try_from(&entity)?;
-                Ok(self.calibrate(entity.id.parse()?, local_location))
+                Ok((
+                    self.calibrate(entity.id.parse()?, local_location),
+                    entity.timestamp(),
+                ))
             }
 
             #[cfg(feature = "websocket")]
@@ -144,7 +160,10 @@ impl Metrics {
                     let entity: WebsocketEntity = ::serde_json::from_str(&message)?;
                     match LocalLocation::try_from(&entity.body) {
                         Ok(local_location) => {
-                            break Ok(self.calibrate(entity.body.id.parse()?, local_location))
+                            break Ok((
+                                self.calibrate(entity.body.id.parse()?, local_location),
+                                entity.body.timestamp(),
+                            ))
                         }
                         Err(_) => continue,
                     }
@@ -215,12 +234,33 @@ impl Entity {
         self.get(key)
             .and_then(|datastream| datastream.parse_value())
     }
+
+    /// Returns when the position was observed, or now if not reported.
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.get("posX")
+            .ok()
+            .and_then(|datastream| datastream.at.as_deref())
+            .and_then(parse_timestamp)
+            .unwrap_or_else(Utc::now)
+    }
 }
 
 #[derive(::serde::Deserialize)]
 struct DataStream {
     id: String,
     current_value: String,
+    #[serde(default)]
+    at: Option<String>,
+}
+
+/// Parses the timestamps of RFC 3339, or of `2006-01-02 15:04:05.000` in UTC.
+fn parse_timestamp(at: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(at)
+        .map(|at| at.to_utc())
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(at.trim(), "%Y-%m-%d %H:%M:%S%.f").map(|at| at.and_utc())
+        })
+        .ok()
 }
 
 impl DataStream {
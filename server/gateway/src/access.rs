@@ -0,0 +1,606 @@
+use std::{collections::HashSet, future::ready, sync::Arc};
+
+use actix_web::{
+    dev::Payload,
+    http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL},
+    web::Data,
+    FromRequest, HttpRequest,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use footprint_api::DataRef;
+use footprint_client::LocationStore;
+use futures::{future::LocalBoxFuture, stream, StreamExt};
+
+use crate::{
+    auth::{AuthPolicy, Identity},
+    error::{ApiError, Result},
+    privacy::{PrivacyPolicy, PrivateStore},
+};
+
+pub const VERB_GET: &str = "get";
+pub const VERB_UPDATE: &str = "update";
+
+/// The maximum number of the objects to authorize concurrently.
+pub const MAX_CONCURRENT_CHECKS: usize = 32;
+
+/// The prefix of the WebSocket subprotocol carrying the base64url-encoded bearer token,
+/// as browsers cannot set the `Authorization` header on WebSockets.
+pub const PROTOCOL_BEARER_PREFIX: &str = "base64url.bearer.footprint.ulagbulag.io.";
+
+/// Returns the bearer token from the `Authorization` header, or the WebSocket subprotocols.
+fn bearer_token(request: &HttpRequest) -> Option<String> {
+    let headers = request.headers();
+    if let Some(value) = headers.get(AUTHORIZATION) {
+        return value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+    }
+
+    headers
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|protocol| protocol.trim().strip_prefix(PROTOCOL_BEARER_PREFIX))
+        .and_then(|token| URL_SAFE_NO_PAD.decode(token.trim_end_matches('=')).ok())
+        .and_then(|token| String::from_utf8(token).ok())
+}
+
+/// The caller of a request, authenticated by exactly one of [`AuthPolicy`] or `TokenReview`
+/// and authorized by the rules or the RBAC of the referenced objects respectively;
+/// otherwise every object is allowed.
+#[derive(Clone, Default)]
+pub struct Access {
+    identity: Option<(Data<AuthPolicy>, Identity)>,
+    privacy: Option<Data<PrivacyPolicy>>,
+    #[cfg(feature = "kubernetes")]
+    caller: Option<(
+        Data<self::kubernetes::Authorizer>,
+        ::k8s_openapi::api::authentication::v1::UserInfo,
+    )>,
+}
+
+impl Access {
+    /// Fails if the object does not exist, or the caller is not allowed to `verb` it.
+    pub async fn check(&self, data: &DataRef, verb: &'static str) -> Result<()> {
+        if let Some((policy, identity)) = &self.identity {
+            if !policy.is_allowed(identity, data, verb) {
+                return Err(ApiError::forbidden(format!(
+                    "{} is not allowed to {verb} the object",
+                    identity.user,
+                )));
+            }
+        }
+
+        #[cfg(feature = "kubernetes")]
+        if let Some((authorizer, user)) = &self.caller {
+            if !authorizer.is_allowed(user, data, verb).await? {
+                return Err(ApiError::forbidden(format!(
+                    "not allowed to {verb} the object"
+                )));
+            }
+            if !authorizer.exists(data).await? {
+                return Err(ApiError::not_found("no such object"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the store exposing the locations to the caller, applying the [`PrivacyPolicy`].
+    pub fn store(&self, store: Arc<dyn LocationStore>) -> PrivateStore {
+        PrivateStore::new(store, self.privacy.clone(), self.caller())
+    }
+
+    fn caller(&self) -> Option<Identity> {
+        if let Some((_, identity)) = &self.identity {
+            return Some(identity.clone());
+        }
+
+        #[cfg(feature = "kubernetes")]
+        if let Some((_, user)) = &self.caller {
+            return Some(Identity {
+                user: user.username.clone().unwrap_or_default(),
+                groups: user.groups.clone().unwrap_or_default(),
+            });
+        }
+        None
+    }
+
+    /// Returns whether the caller can get the object.
+    pub async fn is_allowed(&self, data: &DataRef) -> bool {
+        self.check(data, VERB_GET).await.is_ok()
+    }
+
+    /// Retains the items of the objects which the caller can get.
+    pub async fn retain<T>(&self, items: Vec<T>, f: impl Fn(&T) -> &DataRef) -> Vec<T> {
+        #[cfg(feature = "kubernetes")]
+        let is_restricted = self.identity.is_some() || self.caller.is_some();
+        #[cfg(not(feature = "kubernetes"))]
+        let is_restricted = self.identity.is_some();
+        if !is_restricted {
+            return items;
+        }
+
+        // review one object per namespace and kind first, not to race the rest on the cache
+        let mut reviewed = HashSet::new();
+        stream::iter(items.iter().map(&f))
+            .filter(|data| ready(reviewed.insert((&data.namespace, &data.kind))))
+            .for_each_concurrent(MAX_CONCURRENT_CHECKS, |data| async move {
+                self.is_allowed(data).await;
+            })
+            .await;
+
+        let mut allowed: Vec<_> = stream::iter(items.into_iter().enumerate())
+            .map(|(index, item)| {
+                let f = &f;
+                async move { self.is_allowed(f(&item)).await.then_some((index, item)) }
+            })
+            .buffer_unordered(MAX_CONCURRENT_CHECKS)
+            .filter_map(|item| async move { item })
+            .collect()
+            .await;
+
+        // keep the original order for the pagination
+        allowed.sort_unstable_by_key(|(index, _)| *index);
+        allowed.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+impl FromRequest for Access {
+    type Error = ApiError;
+    type Future = LocalBoxFuture<'static, Result<Self>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = bearer_token(request);
+
+        let policy = request.app_data::<Data<AuthPolicy>>().cloned();
+
+        let privacy = request.app_data::<Data<PrivacyPolicy>>().cloned();
+
+        #[cfg(feature = "kubernetes")]
+        let authorizer = request
+            .app_data::<Data<self::kubernetes::Authorizer>>()
+            .cloned();
+
+        Box::pin(async move {
+            // try the policy first, falling back to the TokenReview for the other tokens
+            let identity = match policy {
+                Some(policy) => match policy.authenticate(token.as_deref()) {
+                    Ok(identity) => Some((policy, identity)),
+                    #[cfg(feature = "kubernetes")]
+                    Err(_) if authorizer.is_some() && token.is_some() => None,
+                    Err(error) => return Err(error),
+                },
+                None => None,
+            };
+
+            Ok(Access {
+                #[cfg(feature = "kubernetes")]
+                caller: match authorizer {
+                    Some(authorizer) if identity.is_none() => {
+                        let token =
+                            token.ok_or_else(|| ApiError::unauthorized("missing bearer token"))?;
+                        let user = authorizer
+                            .authenticate(&token)
+                            .await?
+                            .ok_or_else(|| ApiError::unauthorized("invalid bearer token"))?;
+                        Some((authorizer, user))
+                    }
+                    _ => None,
+                },
+                identity,
+                privacy,
+            })
+        })
+    }
+}
+
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes {
+    use std::{
+        collections::HashMap,
+        hash::Hash,
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    use footprint_api::{DataRef, ErrorCode};
+    use k8s_openapi::api::{
+        authentication::v1::{TokenReview, TokenReviewSpec, UserInfo},
+        authorization::v1::{ResourceAttributes, SubjectAccessReview, SubjectAccessReviewSpec},
+    };
+    use kube::{
+        api::{ApiResource, DynamicObject, PostParams},
+        Api, Client,
+    };
+    use sha2::{Digest, Sha256};
+
+    use crate::error::{ApiError, Result};
+
+    /// Validates the callers with `TokenReview`, and their access to the referenced objects
+    /// with `SubjectAccessReview`, caching the results for a while.
+    ///
+    /// The access is reviewed per namespace and kind, not per object, so that the
+    /// listings of many objects cost a few reviews; `resourceNames` rules are not honored.
+    pub struct Authorizer {
+        client: Client,
+        cache_ttl: Duration,
+        users: Cache<String, Option<UserInfo>>,
+        /// Keyed by the digest of the whole user, as the access may depend on any of its fields
+        reviews: Cache<(String, Option<String>, String, &'static str), bool>,
+        objects: Cache<DataRef, bool>,
+    }
+
+    impl Authorizer {
+        pub fn new(client: Client, cache_ttl: Duration) -> Self {
+            Self {
+                client,
+                cache_ttl,
+                users: Cache::default(),
+                reviews: Cache::default(),
+                objects: Cache::default(),
+            }
+        }
+
+        /// Returns the user of the token, if valid.
+        pub async fn authenticate(&self, token: &str) -> Result<Option<UserInfo>> {
+            // do not keep the raw tokens in memory
+            let key = ::hex::encode(Sha256::digest(token));
+            if let Some(user) = self.users.get(&key, self.cache_ttl) {
+                return Ok(user);
+            }
+
+            let review = TokenReview {
+                spec: TokenReviewSpec {
+                    token: Some(token.into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let user = Api::<TokenReview>::all(self.client.clone())
+                .create(&PostParams::default(), &review)
+                .await
+                .map_err(kube_error)?
+                .status
+                .filter(|status| status.authenticated == Some(true))
+                .and_then(|status| status.user);
+
+            self.users.insert(key, user.clone());
+            Ok(user)
+        }
+
+        pub async fn is_allowed(
+            &self,
+            user: &UserInfo,
+            data: &DataRef,
+            verb: &'static str,
+        ) -> Result<bool> {
+            let Some((group, version, plural)) = data.resource() else {
+                return Ok(false);
+            };
+            let namespace = data.namespace.clone().filter(|ns| !ns.is_empty());
+            let key = (user_key(user), namespace.clone(), data.kind.clone(), verb);
+            if let Some(allowed) = self.reviews.get(&key, self.cache_ttl) {
+                return Ok(allowed);
+            }
+
+            let review = SubjectAccessReview {
+                spec: SubjectAccessReviewSpec {
+                    user: user.username.clone(),
+                    uid: user.uid.clone(),
+                    groups: user.groups.clone(),
+                    extra: user.extra.clone(),
+                    resource_attributes: Some(ResourceAttributes {
+                        group: Some(group.into()),
+                        version: Some(version.into()),
+                        resource: Some(plural.into()),
+                        namespace,
+                        verb: Some(verb.into()),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let allowed = Api::<SubjectAccessReview>::all(self.client.clone())
+                .create(&PostParams::default(), &review)
+                .await
+                .map_err(kube_error)?
+                .status
+                .is_some_and(|status| status.allowed);
+
+            self.reviews.insert(key, allowed);
+            Ok(allowed)
+        }
+
+        /// Returns whether the object exists, with the permission of the gateway.
+        pub async fn exists(&self, data: &DataRef) -> Result<bool> {
+            if let Some(exists) = self.objects.get(data, self.cache_ttl) {
+                return Ok(exists);
+            }
+
+            let exists = get_object(&self.client, data)
+                .await
+                .map_err(kube_error)?
+                .is_some();
+
+            self.objects.insert(data.clone(), exists);
+            Ok(exists)
+        }
+    }
+
+    /// Returns the digest of the user, regardless of the order of the groups and the extra values.
+    fn user_key(user: &UserInfo) -> String {
+        let mut user = user.clone();
+        if let Some(groups) = &mut user.groups {
+            groups.sort_unstable();
+        }
+        if let Some(extra) = &mut user.extra {
+            extra.values_mut().for_each(|values| values.sort_unstable());
+        }
+        let user = ::serde_json::to_vec(&user).expect("the user should be serializable");
+        ::hex::encode(Sha256::digest(user))
+    }
+
+    /// Returns the referenced object, if exists.
+    pub async fn get_object(
+        client: &Client,
+        data: &DataRef,
+    ) -> Result<Option<DynamicObject>, ::kube::Error> {
+        let Some((group, version, plural)) = data.resource() else {
+            return Ok(None);
+        };
+        let resource = ApiResource {
+            group: group.into(),
+            version: version.into(),
+            api_version: if group.is_empty() {
+                version.into()
+            } else {
+                format!("{group}/{version}")
+            },
+            kind: String::default(),
+            plural: plural.into(),
+        };
+        let api = match data.namespace.as_deref().filter(|ns| !ns.is_empty()) {
+            Some(namespace) => {
+                Api::<DynamicObject>::namespaced_with(client.clone(), namespace, &resource)
+            }
+            None => Api::<DynamicObject>::all_with(client.clone(), &resource),
+        };
+        match api.get_opt(&data.name).await {
+            Ok(object) => Ok(object),
+            // no such resource type
+            Err(::kube::Error::Api(response)) if response.code == 404 => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn kube_error(error: ::kube::Error) -> ApiError {
+        ApiError::new(ErrorCode::BadGateway, "failed to review the access").with_detail(error)
+    }
+
+    pub struct Cache<K, V>(Mutex<HashMap<K, (Instant, V)>>);
+
+    impl<K, V> Default for Cache<K, V> {
+        fn default() -> Self {
+            Self(Mutex::default())
+        }
+    }
+
+    impl<K, V> Cache<K, V>
+    where
+        K: Eq + Hash,
+        V: Clone,
+    {
+        const MAX_ENTRIES: usize = 65_536;
+
+        pub fn get(&self, key: &K, ttl: Duration) -> Option<V> {
+            let entries = self.0.lock().unwrap();
+            entries
+                .get(key)
+                .filter(|(created_at, _)| created_at.elapsed() < ttl)
+                .map(|(_, value)| value.clone())
+        }
+
+        pub fn insert(&self, key: K, value: V) {
+            let mut entries = self.0.lock().unwrap();
+            if entries.len() >= Self::MAX_ENTRIES {
+                entries.clear();
+            }
+            entries.insert(key, (Instant::now(), value));
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::BTreeMap;
+
+        use super::*;
+
+        fn user() -> UserInfo {
+            UserInfo {
+                username: Some("alice".into()),
+                uid: Some("1".into()),
+                groups: Some(vec!["a".into(), "b".into()]),
+                extra: Some(BTreeMap::from([(
+                    "scopes".into(),
+                    vec!["x".into(), "y".into()],
+                )])),
+            }
+        }
+
+        #[test]
+        fn distinguish_users_by_all_fields() {
+            let key = user_key(&user());
+
+            let mut reordered = user();
+            reordered.groups.as_mut().unwrap().reverse();
+            for values in reordered.extra.as_mut().unwrap().values_mut() {
+                values.reverse();
+            }
+            assert_eq!(user_key(&reordered), key);
+
+            let mut other = user();
+            other.uid = Some("2".into());
+            assert_ne!(user_key(&other), key);
+
+            let mut other = user();
+            other.groups = Some(vec!["a".into()]);
+            assert_ne!(user_key(&other), key);
+
+            let mut other = user();
+            other.extra = None;
+            assert_ne!(user_key(&other), key);
+        }
+
+        #[test]
+        fn expire_cache() {
+            let cache = Cache::default();
+            cache.insert("key", true);
+            assert_eq!(cache.get(&"key", Duration::from_secs(60)), Some(true));
+            assert_eq!(cache.get(&"key", Duration::ZERO), None);
+            assert_eq!(cache.get(&"other", Duration::from_secs(60)), None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn read_bearer_token() {
+        let request = TestRequest::default()
+            .insert_header((AUTHORIZATION, "Bearer alice"))
+            .to_http_request();
+        assert_eq!(bearer_token(&request).as_deref(), Some("alice"));
+
+        let request = TestRequest::default()
+            .insert_header((
+                SEC_WEBSOCKET_PROTOCOL,
+                format!("footprint.v1, {PROTOCOL_BEARER_PREFIX}YWxpY2U"),
+            ))
+            .to_http_request();
+        assert_eq!(bearer_token(&request).as_deref(), Some("alice"));
+
+        let request = TestRequest::default()
+            .insert_header((SEC_WEBSOCKET_PROTOCOL, "footprint.v1"))
+            .to_http_request();
+        assert_eq!(bearer_token(&request), None);
+    }
+
+    /// Reviews the tokens of the given user, returning the reviewed tokens.
+    #[cfg(feature = "kubernetes")]
+    fn serve_token_reviews(user: &'static str) -> (String, ::std::sync::mpsc::Receiver<String>) {
+        use std::{
+            io::{BufRead, BufReader, Read, Write},
+            net::TcpListener,
+            sync::mpsc,
+            thread,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut len = 0;
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    if name.eq_ignore_ascii_case("Content-Length") {
+                        len = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let review: ::serde_json::Value = ::serde_json::from_slice(&body).unwrap();
+                let _ = tx.send(review["spec"]["token"].as_str().unwrap().to_string());
+
+                let body = ::serde_json::json!({
+                    "apiVersion": "authentication.k8s.io/v1",
+                    "kind": "TokenReview",
+                    "spec": {},
+                    "status": { "authenticated": true, "user": { "username": user } },
+                })
+                .to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\n\
+                    Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len(),
+                )
+                .unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    #[cfg(feature = "kubernetes")]
+    #[actix_web::test]
+    async fn fall_back_to_token_review() {
+        use std::{env, fs, process, time::Duration};
+
+        use sha2::{Digest, Sha256};
+
+        let (url, reviews) = serve_token_reviews("alice");
+        let client = ::kube::Client::try_from(::kube::Config::new(url.parse().unwrap())).unwrap();
+        let authorizer = Data::new(self::kubernetes::Authorizer::new(
+            client,
+            Duration::from_secs(60),
+        ));
+
+        let path = env::temp_dir().join(format!("footprint-access-policy-{}", process::id()));
+        let policy = ::serde_json::json!({
+            "api_keys": [{ "sha256": ::hex::encode(Sha256::digest("key-reader")), "user": "reader" }],
+        });
+        fs::write(&path, policy.to_string()).unwrap();
+        let policy = Data::new(AuthPolicy::load(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let access = |token: &str| {
+            let request = TestRequest::default()
+                .insert_header((AUTHORIZATION, format!("Bearer {token}")))
+                .app_data(Data::clone(&policy))
+                .app_data(Data::clone(&authorizer))
+                .to_http_request();
+            Access::from_request(&request, &mut Payload::None)
+        };
+
+        // accepted by the policy, without reviewing the token
+        let access_reader = access("key-reader").await.unwrap();
+        assert_eq!(access_reader.caller().unwrap().user, "reader");
+        assert!(access_reader.caller.is_none());
+        assert!(reviews.try_recv().is_err());
+
+        // rejected by the policy, but accepted by the TokenReview
+        let access_alice = access("token-alice").await.unwrap();
+        assert!(access_alice.identity.is_none());
+        assert_eq!(access_alice.caller().unwrap().user, "alice");
+        assert_eq!(reviews.try_recv().unwrap(), "token-alice");
+    }
+
+    #[actix_web::test]
+    async fn allow_all_without_policies() {
+        let data = DataRef {
+            kind: "user".into(),
+            name: "a".into(),
+            namespace: None,
+        };
+        let access = Access::default();
+        assert!(access.is_allowed(&data).await);
+        assert_eq!(access.retain(vec![data.clone()], |data| data).await, [data]);
+    }
+}
@@ -0,0 +1,377 @@
+use std::{collections::HashMap, fs, path::Path, path::PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use footprint_api::{DataRef, DataRefFilter};
+use footprint_client::DataRefMatcher;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    access::{VERB_GET, VERB_UPDATE},
+    error::ApiError,
+};
+
+/// An authenticated caller.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub user: String,
+    pub groups: Vec<String>,
+}
+
+/// Authenticates the callers with the static API keys or the JWTs, and authorizes
+/// them with the rules on the objects, loaded once from a JSON file.
+///
+/// Both of the API keys and the JWTs are given as the bearer token.
+pub struct AuthPolicy {
+    api_keys: HashMap<String, Identity>,
+    jwt: Option<JwtValidator>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+struct AuthPolicyFile {
+    #[serde(default)]
+    api_keys: Vec<ApiKey>,
+    #[serde(default)]
+    jwt: Option<JwtConfig>,
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize)]
+struct ApiKey {
+    /// Hex-encoded SHA-256 of the key, not to store the raw keys
+    sha256: String,
+    user: String,
+    #[serde(default)]
+    groups: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct JwtConfig {
+    /// A local JWKS file of the issuer, e.g. `/.well-known/jwks.json` of the OIDC provider
+    jwks_path: PathBuf,
+    #[serde(default)]
+    issuer: Option<String>,
+    #[serde(default)]
+    audience: Option<String>,
+    #[serde(default = "JwtConfig::default_user_claim")]
+    user_claim: String,
+    #[serde(default = "JwtConfig::default_groups_claim")]
+    groups_claim: String,
+}
+
+impl JwtConfig {
+    fn default_user_claim() -> String {
+        "sub".into()
+    }
+
+    fn default_groups_claim() -> String {
+        "groups".into()
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleConfig {
+    /// The users to be allowed, or `*` for everyone authenticated
+    #[serde(default)]
+    users: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+    /// Regular expressions (fully anchored) on the objects, e.g. `{"namespace": "vine|ops"}`
+    #[serde(default)]
+    objects: DataRefFilter,
+    #[serde(default = "RuleConfig::default_verbs")]
+    verbs: Vec<String>,
+}
+
+impl RuleConfig {
+    fn default_verbs() -> Vec<String> {
+        vec![VERB_GET.into()]
+    }
+}
+
+struct Rule {
+    users: Vec<String>,
+    groups: Vec<String>,
+    objects: DataRefMatcher,
+    verbs: Vec<String>,
+}
+
+impl Rule {
+    fn is_allowed(&self, identity: &Identity, data: &DataRef, verb: &str) -> bool {
+        let is_subject = self
+            .users
+            .iter()
+            .any(|user| user == "*" || *user == identity.user)
+            || self
+                .groups
+                .iter()
+                .any(|group| identity.groups.contains(group));
+        is_subject && self.verbs.iter().any(|v| v == verb) && self.objects.is_match(data)
+    }
+}
+
+impl AuthPolicy {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let AuthPolicyFile {
+            api_keys,
+            jwt,
+            rules,
+        } = fs::read(path)
+            .map_err(|error| anyhow!("failed to read the auth policy: {path:?}: {error}"))
+            .and_then(|file| {
+                ::serde_json::from_slice(&file)
+                    .map_err(|error| anyhow!("failed to parse the auth policy: {path:?}: {error}"))
+            })?;
+
+        let api_keys = api_keys
+            .into_iter()
+            .map(|key| {
+                let sha256 = key.sha256.to_lowercase();
+                if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                    bail!(
+                        "the API key of {} should be a hex-encoded SHA-256",
+                        key.user
+                    );
+                }
+                let identity = Identity {
+                    user: key.user,
+                    groups: key.groups,
+                };
+                Ok((sha256, identity))
+            })
+            .collect::<Result<_>>()?;
+
+        let jwt = jwt.map(JwtValidator::load).transpose()?;
+
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                if let Some(verb) = rule
+                    .verbs
+                    .iter()
+                    .find(|verb| ![VERB_GET, VERB_UPDATE].contains(&verb.as_str()))
+                {
+                    bail!("unknown verb: {verb}");
+                }
+                Ok(Rule {
+                    objects: DataRefMatcher::new(&rule.objects)
+                        .map_err(|error| anyhow!("invalid objects of the rule: {error}"))?,
+                    users: rule.users,
+                    groups: rule.groups,
+                    verbs: rule.verbs,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            api_keys,
+            jwt,
+            rules,
+        })
+    }
+
+    /// Returns the caller of the bearer token.
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Identity, ApiError> {
+        let token = token.ok_or_else(|| ApiError::unauthorized("missing bearer token"))?;
+
+        let key = ::hex::encode(Sha256::digest(token));
+        if let Some(identity) = self.api_keys.get(&key) {
+            return Ok(identity.clone());
+        }
+
+        match &self.jwt {
+            Some(jwt) => jwt
+                .validate(token)
+                .map_err(|error| ApiError::unauthorized("invalid bearer token").with_detail(error)),
+            None => Err(ApiError::unauthorized("invalid bearer token")),
+        }
+    }
+
+    /// Returns whether any rule allows the caller to `verb` the object.
+    pub fn is_allowed(&self, identity: &Identity, data: &DataRef, verb: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.is_allowed(identity, data, verb))
+    }
+}
+
+struct JwtValidator {
+    keys: JwkSet,
+    issuer: Option<String>,
+    audience: Option<String>,
+    user_claim: String,
+    groups_claim: String,
+}
+
+impl JwtValidator {
+    fn load(config: JwtConfig) -> Result<Self> {
+        let path = &config.jwks_path;
+        let keys: JwkSet = fs::read(path)
+            .map_err(|error| anyhow!("failed to read the JWKS: {path:?}: {error}"))
+            .and_then(|file| {
+                ::serde_json::from_slice(&file)
+                    .map_err(|error| anyhow!("failed to parse the JWKS: {path:?}: {error}"))
+            })?;
+        if keys.keys.is_empty() {
+            bail!("empty JWKS: {path:?}");
+        }
+
+        Ok(Self {
+            keys,
+            issuer: config.issuer,
+            audience: config.audience,
+            user_claim: config.user_claim,
+            groups_claim: config.groups_claim,
+        })
+    }
+
+    fn validate(&self, token: &str) -> Result<Identity> {
+        let header = decode_header(token)?;
+        let jwk = match &header.kid {
+            Some(kid) => self.keys.find(kid),
+            // the key id can be omitted only if there is a single key
+            None => match self.keys.keys.as_slice() {
+                [jwk] => Some(jwk),
+                _ => None,
+            },
+        }
+        .ok_or_else(|| anyhow!("no such key in the JWKS"))?;
+
+        // the algorithm should match the family of the key
+        let mut validation = Validation::new(header.alg);
+        match &self.issuer {
+            Some(issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims =
+            decode::<Map<String, Value>>(token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+        let user = claims
+            .get(&self.user_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("missing claim: {}", self.user_claim))?
+            .into();
+        let groups = match claims.get(&self.groups_claim) {
+            Some(Value::Array(groups)) => groups
+                .iter()
+                .filter_map(Value::as_str)
+                .map(Into::into)
+                .collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::default(),
+        };
+        Ok(Identity { user, groups })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use chrono::Utc;
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &[u8] = b"supersecretsupersecretsupersecret";
+
+    /// The modulus of an RSA public key, of which no token is signed here.
+    const RSA_N: &str = "py60tfEIR-cje2zqRJ9j184K7OtF4lHzrWiKSZGfNziR-DVNiCW8fy9QPpAnFondoQgD1XWNyMicL039OlMIcfVjBH8yzG268l0V85jzUpHjJSGV7XQecY5KG9Fe5kyomeSMlcjbESDuodzG6xldS1-wmqbN8L4eHlRvgXgSWbhjRO9QONyV-iUe7cC78hmpyovhbCXeg1lyUfJ_Q3T0PwpAqRfFDkqQU_UbWAEKhbSQDsCmMuviSM12NzA7WykB9_Frw3Ja_9bPPxX2KNbdLTPSP8bc1i_THVUsL1eUPRbArdFGYB9OR344Icp0Q5RonLYJm1G94T4U9XkND2zJow";
+
+    fn policy(name: &str) -> AuthPolicy {
+        let dir = env::temp_dir().join(format!("footprint-auth-{name}-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let jwks_path = dir.join("jwks.json");
+        let jwks = json!({
+            "keys": [
+                { "kty": "oct", "kid": "hs", "alg": "HS256", "k": URL_SAFE_NO_PAD.encode(SECRET) },
+                { "kty": "RSA", "kid": "rs", "alg": "RS256", "n": RSA_N, "e": "AQAB" },
+            ],
+        });
+        fs::write(&jwks_path, jwks.to_string()).unwrap();
+
+        let path = dir.join("policy.json");
+        let policy = json!({
+            "api_keys": [{ "sha256": ::hex::encode(Sha256::digest("key-reader")), "user": "reader" }],
+            "jwt": { "jwks_path": jwks_path, "issuer": "https://idp", "audience": "footprint" },
+        });
+        fs::write(&path, policy.to_string()).unwrap();
+
+        let policy = AuthPolicy::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        policy
+    }
+
+    fn token(kid: &str, claims: impl FnOnce(&mut Value)) -> String {
+        let mut value = json!({
+            "sub": "alice",
+            "groups": ["ops"],
+            "iss": "https://idp",
+            "aud": "footprint",
+            "exp": Utc::now().timestamp() + 600,
+        });
+        claims(&mut value);
+
+        let header = Header {
+            kid: Some(kid.into()),
+            ..Header::new(Algorithm::HS256)
+        };
+        encode(&header, &value, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[test]
+    fn accept_valid_credentials() {
+        let policy = policy("valid");
+
+        let identity = policy.authenticate(Some("key-reader")).unwrap();
+        assert_eq!(identity.user, "reader");
+
+        let identity = policy.authenticate(Some(&token("hs", |_| ()))).unwrap();
+        assert_eq!(identity.user, "alice");
+        assert_eq!(identity.groups, ["ops"]);
+    }
+
+    #[test]
+    fn reject_unknown_api_key() {
+        let policy = policy("api-key");
+        assert!(policy.authenticate(Some("key-unknown")).is_err());
+        assert!(policy.authenticate(None).is_err());
+    }
+
+    #[test]
+    fn reject_expired_jwt() {
+        let policy = policy("expired");
+        let expired = token("hs", |claims| {
+            claims["exp"] = json!(Utc::now().timestamp() - 600);
+        });
+        assert!(policy.authenticate(Some(&expired)).is_err());
+    }
+
+    #[test]
+    fn reject_other_audience_or_issuer() {
+        let policy = policy("audience");
+        let other = token("hs", |claims| claims["aud"] = json!("other"));
+        assert!(policy.authenticate(Some(&other)).is_err());
+        let other = token("hs", |claims| claims["iss"] = json!("https://other"));
+        assert!(policy.authenticate(Some(&other)).is_err());
+    }
+
+    #[test]
+    fn reject_hmac_on_rsa_key() {
+        // e.g. signed with the public key as the secret
+        let policy = policy("algorithm");
+        assert!(policy.authenticate(Some(&token("rs", |_| ()))).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+use anyhow::{anyhow, bail, Result};
+use ark_core::env::infer;
+use reqwest::Url;
+
+/// The CORS policy of the gateway, denying any cross-origin request by default.
+#[derive(Clone)]
+pub struct CorsConfig {
+    /// Allows any origin, method and header, only for the development
+    pub dev: bool,
+    /// `None` if any origin is allowed
+    pub origins: Option<Vec<String>>,
+    pub methods: Vec<Method>,
+    pub headers: Vec<HeaderName>,
+    pub credentials: bool,
+    pub max_age_sec: usize,
+}
+
+impl CorsConfig {
+    pub fn try_from_env() -> Result<Self> {
+        let dev = infer("FOOTPRINT_CORS_DEV").unwrap_or(false);
+
+        let origins = parse_list("FOOTPRINT_CORS_ALLOWED_ORIGINS", "", parse_origin)?;
+        let origins = if origins.iter().any(|origin| origin == "*") {
+            None
+        } else {
+            Some(origins)
+        };
+
+        let methods = parse_list(
+            "FOOTPRINT_CORS_ALLOWED_METHODS",
+            if cfg!(feature = "put") {
+                "GET,PUT"
+            } else {
+                "GET"
+            },
+            |method| {
+                Method::from_str(&method.to_uppercase())
+                    .map_err(|error| anyhow!("invalid CORS method: {method}: {error}"))
+            },
+        )?;
+        let headers = parse_list(
+            "FOOTPRINT_CORS_ALLOWED_HEADERS",
+            "Authorization,Content-Type",
+            |header| {
+                HeaderName::from_str(header)
+                    .map_err(|error| anyhow!("invalid CORS header: {header}: {error}"))
+            },
+        )?;
+
+        let credentials = infer("FOOTPRINT_CORS_ALLOW_CREDENTIALS").unwrap_or(false);
+        if credentials && origins.is_none() {
+            bail!("the CORS credentials cannot be allowed to any origin");
+        }
+
+        Ok(Self {
+            dev,
+            origins,
+            methods,
+            headers,
+            credentials,
+            max_age_sec: infer("FOOTPRINT_CORS_MAX_AGE_SEC").unwrap_or(3600),
+        })
+    }
+
+    pub fn build(&self) -> Cors {
+        if self.dev {
+            return Cors::default()
+                .allow_any_header()
+                .allow_any_method()
+                .allow_any_origin();
+        }
+
+        let mut cors = Cors::default()
+            .allowed_methods(self.methods.iter().cloned())
+            .allowed_headers(self.headers.iter().cloned())
+            .max_age(self.max_age_sec);
+        match &self.origins {
+            Some(origins) => {
+                for origin in origins {
+                    cors = cors.allowed_origin(origin);
+                }
+            }
+            None => cors = cors.allow_any_origin(),
+        }
+        if self.credentials {
+            cors = cors.supports_credentials();
+        }
+        cors
+    }
+}
+
+fn parse_list<T>(key: &str, default: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Vec<T>> {
+    infer::<_, String>(key)
+        .unwrap_or_else(|_| default.into())
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(parse)
+        .collect()
+}
+
+/// Validates an origin, e.g. `https://example.com` or `*`.
+fn parse_origin(origin: &str) -> Result<String> {
+    if origin == "*" {
+        return Ok(origin.into());
+    }
+
+    let url =
+        Url::parse(origin).map_err(|error| anyhow!("invalid CORS origin: {origin}: {error}"))?;
+    let serialized = url.origin().ascii_serialization();
+    if serialized != origin.trim_end_matches('/') {
+        bail!("the CORS origin should be a scheme and a host only: {origin}");
+    }
+    Ok(serialized)
+}
@@ -0,0 +1,16 @@
+use std::{env, time::Duration};
+
+use anyhow::{anyhow, Result};
+use ark_core::env::infer;
+
+/// Returns the seconds given by the environment variable as a duration, or `None` if not given.
+pub fn infer_secs(key: &str) -> Result<Option<Duration>> {
+    if env::var_os(key).is_none() {
+        return Ok(None);
+    }
+
+    let secs: f64 = infer(key)?;
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(|error| anyhow!("invalid {key}: {secs}: {error}"))
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use footprint_api::{ErrorCode, ErrorResponse};
+use footprint_client::{Error, PrometheusErrorType};
+
+pub type Result<T, E = ApiError> = ::std::result::Result<T, E>;
+
+/// An error response, serialized as [`ErrorResponse`].
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    body: ErrorResponse,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        let status = match code {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::NotImplemented => StatusCode::NOT_IMPLEMENTED,
+            ErrorCode::BadGateway => StatusCode::BAD_GATEWAY,
+            ErrorCode::GatewayTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self {
+            status,
+            body: ErrorResponse {
+                code,
+                message: message.into(),
+                detail: None,
+            },
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::BadRequest, message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Unauthorized, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Forbidden, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn with_detail(mut self, detail: impl ToString) -> Self {
+        self.body.detail = Some(detail.to_string());
+        self
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.body.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(&self.body)
+    }
+}
+
+impl From<Error> for ApiError {
+    fn from(error: Error) -> Self {
+        let detail = error.to_string();
+        match error {
+            Error::InvalidFilter(_) => Self::bad_request("invalid filter"),
+            Error::TooManyPoints { .. } => Self::bad_request("too many points"),
+            Error::ZeroStep => Self::bad_request("step should be positive"),
+            Error::NotFound => Self::not_found("no such object"),
+            Error::Unsupported(_) => Self::new(ErrorCode::NotImplemented, "unsupported operation"),
+            Error::Prometheus { error_type, .. } => match error_type {
+                PrometheusErrorType::BadData => Self::bad_request("invalid query"),
+                PrometheusErrorType::Canceled | PrometheusErrorType::Timeout => {
+                    Self::new(ErrorCode::GatewayTimeout, "the backend timed out")
+                }
+                _ => Self::new(ErrorCode::BadGateway, "the backend failed"),
+            },
+            Error::Network(error) if error.is_timeout() => {
+                Self::new(ErrorCode::GatewayTimeout, "the backend timed out")
+            }
+            Error::Network(_)
+            | Error::Http { .. }
+            | Error::Decode(_)
+            | Error::UnexpectedResultType(_) => {
+                Self::new(ErrorCode::BadGateway, "the backend failed")
+            }
+            Error::Config(_) | Error::Io { .. } | Error::Url(_) => {
+                Self::new(ErrorCode::Internal, "internal error")
+            }
+        }
+        .with_detail(detail)
+    }
+}
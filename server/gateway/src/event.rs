@@ -0,0 +1,270 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use actix_web::{rt, web::Data};
+use chrono::{DateTime, Utc};
+use footprint_api::{DataRef, GeofenceEvent, GeofenceEventKind, TimedLocation, TimedLocationData};
+use tokio::{
+    select,
+    sync::broadcast::{error::RecvError, Receiver},
+    time::interval,
+};
+
+use crate::{geofence::Geofences, stream::Update, webhook::Webhooks};
+
+/// Tracks the zones of each object from the location updates, and emits the events.
+pub struct EventEngine {
+    geofences: Data<Geofences>,
+    hysteresis_m: f64,
+    states: HashMap<(DataRef, usize), ZoneState>,
+    webhooks: Webhooks,
+}
+
+struct ZoneState {
+    entered_at: DateTime<Utc>,
+    location: TimedLocation,
+    dwelled: bool,
+}
+
+impl EventEngine {
+    pub fn spawn(
+        geofences: Data<Geofences>,
+        hysteresis_m: f64,
+        webhooks: Webhooks,
+        rx: Receiver<Arc<Update>>,
+    ) {
+        rt::spawn(Self::new(geofences, hysteresis_m, webhooks).run(rx));
+    }
+
+    fn new(geofences: Data<Geofences>, hysteresis_m: f64, webhooks: Webhooks) -> Self {
+        Self {
+            geofences,
+            hysteresis_m,
+            states: HashMap::default(),
+            webhooks,
+        }
+    }
+
+    async fn run(mut self, mut rx: Receiver<Arc<Update>>) {
+        let mut ticker = interval(Duration::from_secs(1));
+        loop {
+            select! {
+                update = rx.recv() => match update.as_deref() {
+                    Ok(Update::Location(data)) => self.update(data),
+                    Ok(Update::Removed(data)) => self.remove(data),
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("skipped {skipped} location updates for the geofence events");
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = ticker.tick() => self.tick(Utc::now()),
+            }
+        }
+    }
+
+    fn update(&mut self, TimedLocationData { data, location }: &TimedLocationData) {
+        for (index, geofence) in self.geofences.iter().enumerate() {
+            let distance_m = geofence.distance_m(&location.location);
+            if distance_m.is_nan() {
+                continue;
+            }
+
+            let key = (data.clone(), index);
+            match self.states.get_mut(&key) {
+                None if distance_m == 0.0 => {
+                    let state = ZoneState {
+                        entered_at: location.timestamp,
+                        location: *location,
+                        dwelled: false,
+                    };
+                    self.emit(GeofenceEventKind::Enter, &key, &state);
+                    self.states.insert(key, state);
+                }
+                None => {}
+                // leave only when far enough, not to flap at the boundary
+                Some(_) if distance_m > geofence.hysteresis_m.unwrap_or(self.hysteresis_m) => {
+                    if let Some(mut state) = self.states.remove(&key) {
+                        state.location = *location;
+                        self.emit(GeofenceEventKind::Exit, &key, &state);
+                    }
+                }
+                Some(state) => state.location = *location,
+            }
+        }
+    }
+
+    /// Exits the object from all zones, as it is no longer in the store.
+    fn remove(&mut self, data: &DataRef) {
+        let keys: Vec<_> = self
+            .states
+            .keys()
+            .filter(|(key, _)| key == data)
+            .cloned()
+            .collect();
+        for key in keys {
+            if let Some(state) = self.states.remove(&key) {
+                self.emit(GeofenceEventKind::Exit, &key, &state);
+            }
+        }
+    }
+
+    fn tick(&mut self, now: DateTime<Utc>) {
+        let mut dwelled = Vec::default();
+        for (key, state) in &mut self.states {
+            let Some(dwell_sec) = self.geofences.get(key.1).and_then(|g| g.dwell_sec) else {
+                continue;
+            };
+            if !state.dwelled
+                && (now - state.entered_at)
+                    .to_std()
+                    .is_ok_and(|elapsed| elapsed.as_secs_f64() >= dwell_sec)
+            {
+                state.dwelled = true;
+                dwelled.push(key.clone());
+            }
+        }
+        for key in dwelled {
+            if let Some(state) = self.states.get(&key) {
+                self.emit(GeofenceEventKind::Dwell, &key, state);
+            }
+        }
+    }
+
+    fn emit(&self, kind: GeofenceEventKind, (data, index): &(DataRef, usize), state: &ZoneState) {
+        let Some(geofence) = self.geofences.get(*index) else {
+            return;
+        };
+        self.webhooks.send(&GeofenceEvent {
+            kind,
+            geofence: geofence.name.clone(),
+            labels: geofence.labels.clone(),
+            data: data.clone(),
+            location: state.location,
+            entered_at: state.entered_at,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use footprint_api::{
+        Coordinates, GeofenceEvent, GeofenceShape, GlobalLocation, LocalLocation, Location,
+    };
+    use tokio::sync::mpsc::Receiver;
+
+    use super::*;
+
+    fn engine() -> (EventEngine, Receiver<GeofenceEvent>) {
+        let geofences = [footprint_api::Geofence {
+            name: "room".into(),
+            labels: Default::default(),
+            coordinates: Coordinates::Local,
+            shape: GeofenceShape::Circle {
+                center: [0.0, 0.0],
+                radius_m: 1.0,
+            },
+            dwell_sec: Some(10.0),
+            hysteresis_m: Some(2.0),
+        }]
+        .into_iter()
+        .collect();
+        let (webhooks, rx) = Webhooks::channel(16);
+        (EventEngine::new(Data::new(geofences), 1.0, webhooks), rx)
+    }
+
+    fn data() -> DataRef {
+        DataRef {
+            kind: "user".into(),
+            name: "a".into(),
+            namespace: None,
+        }
+    }
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + ::chrono::Duration::seconds(secs)
+    }
+
+    fn at(secs: i64, x: f64) -> TimedLocationData {
+        TimedLocationData {
+            data: data(),
+            location: TimedLocation {
+                timestamp: time(secs),
+                location: Location {
+                    global: GlobalLocation {
+                        error_m: f64::NAN,
+                        latitude: f64::NAN,
+                        longitude: f64::NAN,
+                    },
+                    local: LocalLocation {
+                        x,
+                        y: 0.0,
+                        error_m: 0.0,
+                    },
+                    motion: Default::default(),
+                },
+            },
+        }
+    }
+
+    fn events(rx: &mut Receiver<GeofenceEvent>) -> Vec<(GeofenceEventKind, DateTime<Utc>)> {
+        let mut events = Vec::default();
+        while let Ok(event) = rx.try_recv() {
+            events.push((event.kind, event.location.timestamp));
+        }
+        events
+    }
+
+    #[test]
+    fn enter_and_exit_with_hysteresis() {
+        let (mut engine, mut rx) = engine();
+
+        engine.update(&at(0, 5.0));
+        assert!(events(&mut rx).is_empty());
+
+        engine.update(&at(1, 0.5));
+        engine.update(&at(2, 0.5));
+        assert_eq!(events(&mut rx), [(GeofenceEventKind::Enter, time(1))]);
+
+        // within the hysteresis of the zone
+        engine.update(&at(3, 2.5));
+        assert!(events(&mut rx).is_empty());
+        // back in the zone, not to enter again
+        engine.update(&at(4, 0.0));
+        assert!(events(&mut rx).is_empty());
+
+        engine.update(&at(5, 3.5));
+        assert_eq!(events(&mut rx), [(GeofenceEventKind::Exit, time(5))]);
+        assert!(engine.states.is_empty());
+    }
+
+    #[test]
+    fn emit_dwell_once() {
+        let (mut engine, mut rx) = engine();
+
+        engine.update(&at(0, 0.0));
+        assert_eq!(events(&mut rx), [(GeofenceEventKind::Enter, time(0))]);
+
+        engine.tick(time(9));
+        assert!(events(&mut rx).is_empty());
+        engine.tick(time(10));
+        assert_eq!(events(&mut rx), [(GeofenceEventKind::Dwell, time(0))]);
+        engine.tick(time(20));
+        assert!(events(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn exit_removed_objects() {
+        let (mut engine, mut rx) = engine();
+
+        engine.update(&at(0, 0.0));
+        engine.update(&at(1, 0.5));
+        assert_eq!(events(&mut rx), [(GeofenceEventKind::Enter, time(0))]);
+
+        // exits at the last known location
+        engine.remove(&data());
+        assert_eq!(events(&mut rx), [(GeofenceEventKind::Exit, time(1))]);
+        assert!(engine.states.is_empty());
+
+        engine.remove(&data());
+        assert!(events(&mut rx).is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse,
+};
+use anyhow::{anyhow, bail, Result};
+use footprint_api::{
+    Coordinates, DataRefFilter, Geofence, GeofenceMembership, GeofenceShape, TimedLocationData,
+};
+use footprint_client::LocationStore;
+use footprint_provider_api::consts;
+use prometheus::{Encoder, GaugeVec, Opts, Registry, TextEncoder};
+
+use crate::access::Access;
+
+/// The geofences, loaded once from a JSON file.
+#[derive(Default)]
+pub struct Geofences(Vec<Geofence>);
+
+impl FromIterator<Geofence> for Geofences {
+    fn from_iter<T: IntoIterator<Item = Geofence>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl Geofences {
+    /// Loads the geofences, rejecting the local ones if the store has no local coordinates,
+    /// as they would never contain any object.
+    pub fn load(path: impl AsRef<Path>, has_local_coordinates: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let geofences: Vec<Geofence> = fs::read(path)
+            .map_err(|error| anyhow!("failed to read the geofences: {path:?}: {error}"))
+            .and_then(|file| {
+                ::serde_json::from_slice(&file)
+                    .map_err(|error| anyhow!("failed to parse the geofences: {path:?}: {error}"))
+            })?;
+
+        let mut names = BTreeSet::default();
+        for geofence in &geofences {
+            let name = &geofence.name;
+            if !names.insert(name) {
+                bail!("duplicated geofence: {name}");
+            }
+            if geofence.coordinates == Coordinates::Local && !has_local_coordinates {
+                bail!("the store has no local coordinates for the geofence: {name}");
+            }
+            match &geofence.shape {
+                GeofenceShape::Circle { radius_m, .. } if radius_m.is_nan() || *radius_m < 0.0 => {
+                    bail!("the radius of the geofence should not be negative: {name}")
+                }
+                GeofenceShape::Polygon { points } if points.len() < 3 => {
+                    bail!("the geofence should have at least 3 points: {name}")
+                }
+                _ => {}
+            }
+        }
+        Ok(Self(geofences))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Geofence> {
+        self.0.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Geofence> {
+        self.0.iter()
+    }
+
+    /// Returns the geofences which the objects matching the filter are in.
+    async fn evaluate(
+        &self,
+        store: &dyn LocationStore,
+        filter: &DataRefFilter,
+    ) -> ::footprint_client::Result<Vec<(TimedLocationData, Vec<&Geofence>)>> {
+        Ok(store
+            .list_timed(filter)
+            .await?
+            .into_iter()
+            .map(|(data, location)| {
+                let geofences = self
+                    .0
+                    .iter()
+                    .filter(|geofence| geofence.contains(&location.location))
+                    .collect();
+                (TimedLocationData { data, location }, geofences)
+            })
+            .collect())
+    }
+}
+
+pub async fn list_geofences(_: Access, geofences: Data<Geofences>) -> HttpResponse {
+    HttpResponse::Ok().json(&geofences.0)
+}
+
+/// Returns the geofences which each object is in.
+pub async fn get_membership(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    geofences: Data<Geofences>,
+    Query(filter): Query<DataRefFilter>,
+) -> crate::error::Result<HttpResponse> {
+    let membership: Vec<_> = geofences
+        .evaluate(&access.store(store.into_inner()), &filter)
+        .await?
+        .into_iter()
+        .map(
+            |(TimedLocationData { data, location }, geofences)| GeofenceMembership {
+                data,
+                location,
+                geofences: geofences
+                    .into_iter()
+                    .map(|geofence| geofence.name.clone())
+                    .collect(),
+            },
+        )
+        .collect();
+    let membership = access.retain(membership, |member| &member.data).await;
+    Ok(HttpResponse::Ok().json(membership))
+}
+
+/// Exports whether each object is in each geofence, as 1 or 0.
+///
+/// Like the other routes, only the objects visible to the caller are exported,
+/// so the scraper needs a credential of its own.
+pub async fn get_metrics(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    geofences: Data<Geofences>,
+) -> crate::error::Result<HttpResponse> {
+    let gauge = GaugeVec::new(
+        Opts::new(
+            consts::METRIC_GEOFENCE_MEMBER,
+            "Geolocational Data: Whether the object is in the geofence",
+        ),
+        &[
+            consts::LABEL_KIND,
+            consts::LABEL_NAME,
+            consts::LABEL_NAMESPACE,
+            consts::LABEL_GEOFENCE,
+        ],
+    )
+    .expect("the geofence gauge should be valid");
+
+    let objects = geofences
+        .evaluate(&access.store(store.into_inner()), &DataRefFilter::default())
+        .await?;
+    for (TimedLocationData { data, .. }, members) in
+        access.retain(objects, |(object, _)| &object.data).await
+    {
+        for geofence in &geofences.0 {
+            let is_member = members.iter().any(|member| member.name == geofence.name);
+            gauge
+                .with_label_values(&[
+                    &data.kind,
+                    &data.name,
+                    data.namespace.as_deref().unwrap_or_default(),
+                    &geofence.name,
+                ])
+                .set(if is_member { 1.0 } else { 0.0 });
+        }
+    }
+
+    let registry = Registry::new();
+    registry
+        .register(Box::new(gauge))
+        .expect("the geofence gauge should be registered once");
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::default();
+    encoder
+        .encode(&registry.gather(), &mut buffer)
+        .expect("the text encoding should not fail");
+    Ok(HttpResponse::Ok()
+        .content_type(encoder.format_type())
+        .body(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use chrono::Utc;
+    use footprint_api::{DataRef, GlobalLocation, LocalLocation, Location, TimedLocation};
+    use footprint_client::MemoryStore;
+
+    use super::*;
+
+    fn load(name: &str, geofences: &str, has_local_coordinates: bool) -> Result<Geofences> {
+        let path = env::temp_dir().join(format!("footprint-geofences-{name}-{}", process::id()));
+        fs::write(&path, geofences).unwrap();
+        let geofences = Geofences::load(&path, has_local_coordinates);
+        fs::remove_file(&path).unwrap();
+        geofences
+    }
+
+    fn data(name: &str) -> DataRef {
+        DataRef {
+            kind: "user".into(),
+            name: name.into(),
+            namespace: None,
+        }
+    }
+
+    fn at(x: f64, y: f64) -> TimedLocation {
+        TimedLocation {
+            timestamp: Utc::now(),
+            location: Location {
+                global: GlobalLocation {
+                    error_m: f64::NAN,
+                    latitude: f64::NAN,
+                    longitude: f64::NAN,
+                },
+                local: LocalLocation { x, y, error_m: 0.0 },
+                motion: Default::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn reject_invalid_geofences() {
+        let circle = r#"{"name":"a","coordinates":"local","shape":{"type":"circle","center":[0,0],"radius_m":1}}"#;
+        assert!(load("valid", &format!("[{circle}]"), true).is_ok());
+        assert!(load("duplicated", &format!("[{circle},{circle}]"), true).is_err());
+        assert!(load("local", &format!("[{circle}]"), false).is_err());
+
+        let negative = r#"[{"name":"a","shape":{"type":"circle","center":[0,0],"radius_m":-1}}]"#;
+        assert!(load("negative", negative, true).is_err());
+        let line = r#"[{"name":"a","shape":{"type":"polygon","points":[[0,0],[1,1]]}}]"#;
+        assert!(load("line", line, true).is_err());
+    }
+
+    #[tokio::test]
+    async fn evaluate_membership() {
+        let geofences: Geofences = [
+            Geofence {
+                name: "room".into(),
+                labels: Default::default(),
+                coordinates: Coordinates::Local,
+                shape: GeofenceShape::Polygon {
+                    points: vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]],
+                },
+                dwell_sec: None,
+                hysteresis_m: None,
+            },
+            Geofence {
+                name: "desk".into(),
+                labels: Default::default(),
+                coordinates: Coordinates::Local,
+                shape: GeofenceShape::Circle {
+                    center: [10.0, 10.0],
+                    radius_m: 1.0,
+                },
+                dwell_sec: None,
+                hysteresis_m: None,
+            },
+        ]
+        .into_iter()
+        .collect();
+
+        let store = MemoryStore::default();
+        store.put(&data("a"), &at(5.0, 5.0)).await.unwrap();
+        store.put(&data("b"), &at(10.0, 10.5)).await.unwrap();
+        store.put(&data("c"), &at(20.0, 20.0)).await.unwrap();
+
+        let membership: Vec<_> = geofences
+            .evaluate(&store, &DataRefFilter::default())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(object, geofences)| {
+                let geofences: Vec<_> = geofences.iter().map(|g| g.name.as_str()).collect();
+                (object.data.name, geofences.join(","))
+            })
+            .collect();
+        assert_eq!(
+            membership,
+            [
+                ("a".to_string(), "room".to_string()),
+                ("b".to_string(), "desk".to_string()),
+                ("c".to_string(), String::new()),
+            ],
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::{fmt::Write, time::Duration};
+
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use footprint_api::{DataRef, TimedLocation};
+use footprint_client::{store::check_history_points, LocationStore};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    access::{Access, VERB_GET},
+    error::{ApiError, Result},
+};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct HistoryQuery {
+    // not flattened, as the query parser cannot parse the numbers of the flattened structs
+    kind: String,
+    name: String,
+    namespace: Option<String>,
+    /// Defaults to an hour before `end`
+    start: Option<DateTime<Utc>>,
+    /// Defaults to now
+    end: Option<DateTime<Utc>>,
+    /// Resolution as seconds, sampling at most 11000 points
+    #[serde(default = "HistoryQuery::default_step")]
+    step: f64,
+    #[serde(default)]
+    format: HistoryFormat,
+}
+
+impl HistoryQuery {
+    const fn default_step() -> f64 {
+        15.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryFormat {
+    #[default]
+    Json,
+    GeoJson,
+    Gpx,
+}
+
+/// Returns the trajectory of an object, sampled by `step`.
+pub async fn get_history(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<HttpResponse> {
+    let data = DataRef {
+        kind: query.kind,
+        name: query.name,
+        namespace: query.namespace,
+    };
+    access.check(&data, VERB_GET).await?;
+
+    let end = query.end.unwrap_or_else(Utc::now);
+    let start = query
+        .start
+        .unwrap_or_else(|| end - ::chrono::Duration::hours(1));
+    let step = match Duration::try_from_secs_f64(query.step) {
+        Ok(step) if !step.is_zero() => step,
+        _ => return Err(ApiError::bad_request("step should be positive")),
+    };
+
+    if start > end {
+        return Err(ApiError::bad_request("start should not be after end"));
+    }
+    check_history_points(start, end, step)?;
+    let trajectory = access
+        .store(store.into_inner())
+        .history(&data, start, end, step)
+        .await?;
+
+    Ok(match query.format {
+        HistoryFormat::Json => HttpResponse::Ok().json(trajectory),
+        HistoryFormat::GeoJson => HttpResponse::Ok()
+            .content_type("application/geo+json")
+            .json(to_geojson(&data, &trajectory)),
+        HistoryFormat::Gpx => HttpResponse::Ok()
+            .content_type("application/gpx+xml")
+            .body(to_gpx(&data, &trajectory)),
+    })
+}
+
+/// Converts the trajectory into a GeoJSON `LineString` feature, with the timestamps of
+/// the coordinates as `coordTimes`.
+fn to_geojson(data: &DataRef, trajectory: &[TimedLocation]) -> ::serde_json::Value {
+    let coordinates: Vec<_> = trajectory
+        .iter()
+        .map(|sample| {
+            [
+                sample.location.global.longitude,
+                sample.location.global.latitude,
+            ]
+        })
+        .collect();
+    let coord_times: Vec<_> = trajectory
+        .iter()
+        .map(|sample| format_timestamp(sample.timestamp))
+        .collect();
+
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": coordinates,
+        },
+        "properties": {
+            "kind": data.kind,
+            "name": data.name,
+            "namespace": data.namespace,
+            "coordTimes": coord_times,
+        },
+    })
+}
+
+/// Converts the trajectory into a GPX 1.1 track.
+fn to_gpx(data: &DataRef, trajectory: &[TimedLocation]) -> String {
+    let name = match &data.namespace {
+        Some(namespace) => format!("{namespace}/{}/{}", data.kind, data.name),
+        None => format!("{}/{}", data.kind, data.name),
+    };
+
+    let mut gpx = String::new();
+    gpx.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    gpx.push('\n');
+    gpx.push_str(
+        r#"<gpx version="1.1" creator="footprint" xmlns="http://www.topografix.com/GPX/1/1">"#,
+    );
+    gpx.push('\n');
+    let _ = writeln!(
+        gpx,
+        "  <trk>\n    <name>{}</name>\n    <trkseg>",
+        escape_xml(&name)
+    );
+    for sample in trajectory {
+        let _ = writeln!(
+            gpx,
+            r#"      <trkpt lat="{lat}" lon="{lon}"><time>{time}</time></trkpt>"#,
+            lat = sample.location.global.latitude,
+            lon = sample.location.global.longitude,
+            time = format_timestamp(sample.timestamp),
+        );
+    }
+    gpx.push_str("    </trkseg>\n  </trk>\n</gpx>\n");
+    gpx
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -1,58 +1,292 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use actix_cors::Cors;
 use actix_web::{
-    get,
-    web::{Data, Query},
+    rt,
+    web::{Data, JsonConfig, Query, QueryConfig},
     App, HttpResponse, HttpServer, Responder,
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use ark_core::{env::infer, tracer};
-use footprint_api::DataRef;
-use footprint_client::Client;
-
-#[get("/")]
-async fn get_metric(client: Data<Client>, Query(query): Query<DataRef>) -> impl Responder {
-    match client.get_raw(&query).await {
-        Ok(data) => HttpResponse::Ok().json(data),
-        Err(e) => HttpResponse::Forbidden().json(e.to_string()),
+use chrono::Utc;
+use footprint_api::{DataRef, DataRefFilter, ObjectList, TimedLocationData};
+use footprint_client::{Client, LocationStore, MemoryStore, SegmentStore, SegmentStoreConfig};
+use regex::escape;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::time::sleep;
+
+use self::{
+    access::{Access, VERB_GET},
+    env::infer_secs,
+    error::ApiError,
+};
+
+mod access;
+mod auth;
+mod cors;
+mod env;
+mod error;
+mod event;
+mod geofence;
+mod history;
+mod openapi;
+mod privacy;
+mod proximity;
+mod stream;
+mod webhook;
+
+async fn get_metric(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    Query(query): Query<DataRef>,
+) -> self::error::Result<HttpResponse> {
+    access.check(&query, VERB_GET).await?;
+    match access.store(store.into_inner()).get(&query).await? {
+        Some(data) => Ok(HttpResponse::Ok().json(data)),
+        None => Err(ApiError::not_found("no such object")),
     }
 }
 
-#[get("/health")]
+/// The maximum number of the objects in a page.
+const MAX_OBJECTS_LIMIT: usize = 1_000;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ObjectsQuery {
+    kind: Option<String>,
+    namespace: Option<String>,
+    name_prefix: Option<String>,
+    /// Skips the objects not updated within the given seconds
+    max_age_sec: Option<f64>,
+    #[serde(default)]
+    offset: usize,
+    /// The maximum number of the objects in a page, up to 1000 (default)
+    limit: Option<usize>,
+}
+
+async fn list_objects(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    Query(query): Query<ObjectsQuery>,
+) -> self::error::Result<HttpResponse> {
+    let limit = match query.limit {
+        Some(0) => return Err(ApiError::bad_request("limit should be positive")),
+        Some(limit) => limit.min(MAX_OBJECTS_LIMIT),
+        None => MAX_OBJECTS_LIMIT,
+    };
+    let max_age = query
+        .max_age_sec
+        .map(|max_age| {
+            Duration::try_from_secs_f64(max_age)
+                .ok()
+                .and_then(|max_age| ::chrono::Duration::from_std(max_age).ok())
+                .ok_or_else(|| ApiError::bad_request("invalid max_age_sec"))
+        })
+        .transpose()?;
+
+    let filter = DataRefFilter {
+        kind: query.kind.as_deref().map(escape),
+        name: query
+            .name_prefix
+            .as_deref()
+            .map(|prefix| format!("{}.*", escape(prefix))),
+        namespace: query.namespace.as_deref().map(escape),
+    };
+    let objects = access.store(store.into_inner()).list_timed(&filter).await?;
+
+    let now = Utc::now();
+    let items: Vec<_> = objects
+        .into_iter()
+        .filter(|(_, location)| match max_age {
+            Some(max_age) => now - location.timestamp <= max_age,
+            None => true,
+        })
+        .map(|(data, location)| TimedLocationData { data, location })
+        .collect();
+    let items = access.retain(items, |item| &item.data).await;
+
+    let total = items.len();
+    let items: Vec<_> = items.into_iter().skip(query.offset).take(limit).collect();
+    let next_offset = Some(query.offset.saturating_add(items.len())).filter(|&next| next < total);
+
+    Ok(HttpResponse::Ok().json(ObjectList {
+        items,
+        total,
+        next_offset,
+    }))
+}
+
+#[cfg(feature = "put")]
+async fn put(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    ::actix_web::web::Json(data): ::actix_web::web::Json<::footprint_api::LocationData>,
+) -> self::error::Result<HttpResponse> {
+    let ::footprint_api::LocationData { data, location } = data;
+    access.check(&data, self::access::VERB_UPDATE).await?;
+    let location = ::footprint_api::TimedLocation {
+        timestamp: Utc::now(),
+        location,
+    };
+    store.put(&data, &location).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
 async fn health() -> impl Responder {
     HttpResponse::Ok().json("healthy")
 }
 
+fn init_store() -> Result<Arc<dyn LocationStore>> {
+    match infer::<_, String>("FOOTPRINT_STORE")
+        .unwrap_or_else(|_| "prometheus".into())
+        .as_str()
+    {
+        "memory" => {
+            let mut store = match infer("FOOTPRINT_STORE_MAX_SAMPLES") {
+                Ok(max_samples) => MemoryStore::new(max_samples),
+                Err(_) => MemoryStore::default(),
+            };
+            if let Some(lookback) = infer_secs("FOOTPRINT_STORE_LOOKBACK_SEC")? {
+                store = store.with_lookback(lookback);
+            }
+            if let Some(retention) = infer_secs("FOOTPRINT_STORE_RETENTION_SEC")? {
+                store = store.with_retention(retention);
+            }
+            Ok(Arc::new(store))
+        }
+        "prometheus" => Ok(Arc::new(Client::try_default()?)),
+        "segment" => {
+            let mut config = SegmentStoreConfig::new(
+                infer::<_, String>("FOOTPRINT_STORE_PATH")
+                    .unwrap_or_else(|_| "/var/lib/footprint".into()),
+            );
+            if let Some(segment) = infer_secs("FOOTPRINT_STORE_SEGMENT_SEC")? {
+                config.segment = segment;
+            }
+            config.retention = infer_secs("FOOTPRINT_STORE_RETENTION_SEC")?;
+            config.downsample_after = infer_secs("FOOTPRINT_STORE_DOWNSAMPLE_AFTER_SEC")?;
+            if let Some(step) = infer_secs("FOOTPRINT_STORE_DOWNSAMPLE_STEP_SEC")? {
+                config.downsample_step = step;
+            }
+            if let Some(sync_interval) = infer_secs("FOOTPRINT_STORE_SYNC_SEC")? {
+                config.sync_interval = sync_interval;
+            }
+            let sync_interval = config.sync_interval;
+            let store = SegmentStore::open(config)?;
+
+            // fsync the last records, even if the writes pause
+            if !sync_interval.is_zero() {
+                let store = store.clone();
+                rt::spawn(async move {
+                    loop {
+                        sleep(sync_interval).await;
+                        if let Err(error) = store.sync().await {
+                            eprintln!("failed to sync the segments: {error}");
+                        }
+                    }
+                });
+            }
+            Ok(Arc::new(store))
+        }
+        store => bail!("unknown footprint store: {store}"),
+    }
+}
+
 #[actix_web::main]
 async fn main() {
     async fn try_main() -> Result<()> {
         // Initialize kubernetes client
         let addr =
             infer::<_, SocketAddr>("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:80".parse().unwrap());
-        let client = Data::new(Client::try_default()?);
+        let store = init_store()?;
+        let stream_interval =
+            infer_secs("FOOTPRINT_STREAM_INTERVAL_SEC")?.unwrap_or(Duration::from_secs(1));
+        if stream_interval.is_zero() {
+            bail!("FOOTPRINT_STREAM_INTERVAL_SEC should be positive");
+        }
+        let geofences = Data::new(match infer::<_, String>("FOOTPRINT_GEOFENCES_PATH") {
+            Ok(path) => self::geofence::Geofences::load(path, store.has_local_coordinates())?,
+            Err(_) => Default::default(),
+        });
+        let privacy = match infer::<_, String>("FOOTPRINT_PRIVACY_PATH") {
+            Ok(path) => Some(Data::new(
+                self::privacy::PrivacyPolicy::load(path, &geofences).await?,
+            )),
+            Err(_) => None,
+        };
+        let broadcaster = Data::from(self::stream::Broadcaster::spawn(
+            Arc::clone(&store),
+            privacy.clone(),
+            stream_interval,
+        ));
+        let store = Data::from(store);
+        if let Some(config) = self::webhook::WebhookConfig::try_from_env()? {
+            self::event::EventEngine::spawn(
+                Data::clone(&geofences),
+                infer("FOOTPRINT_EVENTS_HYSTERESIS_M").unwrap_or(1.0),
+                self::webhook::Webhooks::spawn(config)?,
+                broadcaster.subscribe(),
+            );
+        }
+        let cors = self::cors::CorsConfig::try_from_env()?;
+        if cors.dev {
+            eprintln!("warning: CORS is allowed to any origin in the dev mode");
+        }
+        let auth_policy = match infer::<_, String>("FOOTPRINT_AUTH_PATH") {
+            Ok(path) => Some(Data::new(self::auth::AuthPolicy::load(path)?)),
+            Err(_) => None,
+        };
+        #[cfg(feature = "kubernetes")]
+        let authorizer = if infer("FOOTPRINT_KUBERNETES_AUTHZ").unwrap_or(false) {
+            Some(Data::new(self::access::kubernetes::Authorizer::new(
+                ::kube::Client::try_default().await?,
+                infer_secs("FOOTPRINT_AUTHZ_CACHE_SEC")?.unwrap_or(Duration::from_secs(30)),
+            )))
+        } else {
+            None
+        };
+        #[cfg(feature = "kubernetes")]
+        let is_open = auth_policy.is_none() && authorizer.is_none();
+        #[cfg(not(feature = "kubernetes"))]
+        let is_open = auth_policy.is_none();
+        if is_open {
+            eprintln!(
+                "warning: any caller can access every object without FOOTPRINT_AUTH_PATH or FOOTPRINT_KUBERNETES_AUTHZ"
+            );
+        }
 
         // Start web server
         HttpServer::new(move || {
-            let cors = Cors::default()
-                .allow_any_header()
-                .allow_any_method()
-                .allow_any_origin();
-
             let app = App::new()
-                .app_data(Data::clone(&client))
-                .service(get_metric)
-                .service(health)
-                .wrap(cors);
-
-            #[cfg(feature = "put")]
-            {
-                app.service(put)
-            }
-            #[cfg(not(feature = "put"))]
-            {
-                app
-            }
+                .app_data(QueryConfig::default().error_handler(|error, _| {
+                    ApiError::bad_request("invalid query")
+                        .with_detail(error)
+                        .into()
+                }))
+                .app_data(JsonConfig::default().error_handler(|error, _| {
+                    ApiError::bad_request("invalid body")
+                        .with_detail(error)
+                        .into()
+                }))
+                .app_data(Data::clone(&store))
+                .app_data(Data::clone(&broadcaster))
+                .app_data(Data::clone(&geofences))
+                .configure(self::openapi::configure)
+                .wrap(cors.build());
+
+            let app = match &privacy {
+                Some(privacy) => app.app_data(Data::clone(privacy)),
+                None => app,
+            };
+            let app = match &auth_policy {
+                Some(auth_policy) => app.app_data(Data::clone(auth_policy)),
+                None => app,
+            };
+            #[cfg(feature = "kubernetes")]
+            let app = match &authorizer {
+                Some(authorizer) => app.app_data(Data::clone(authorizer)),
+                None => app,
+            };
+            app
         })
         .bind(addr)
         .unwrap_or_else(|e| panic!("failed to bind to {addr}: {e}"))
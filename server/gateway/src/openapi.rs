@@ -0,0 +1,254 @@
+use std::sync::OnceLock;
+
+use actix_web::{
+    guard,
+    http::Method,
+    web::{self, ServiceConfig},
+    HttpResponse, Responder,
+};
+use footprint_api::{
+    openapi::{OpenApi, Route},
+    DataRef, DataRefFilter, Geofence, GeofenceMembership, Location, NearbyObject, ObjectList,
+    StreamLagged, Subscription, TimedLocation, TimedLocationData,
+};
+use serde_json::{json, Value};
+
+const DESCRIPTION: &str = "\
+Real-time location information of the heterogeneous physical resources.
+
+The bearer token (an API key, a JWT or a Kubernetes token) is required on every endpoint \
+but `/health` and `/openapi.json` if the gateway enforces an auth policy or the Kubernetes RBAC, \
+and only the objects allowed to the caller are returned.
+
+Every failure is returned as an `ErrorResponse` with one of the status codes below:
+
+| Status | Code              | When                                                  |
+| ------ | ----------------- | ----------------------------------------------------- |
+| 400    | `bad_request`     | Malformed parameters, filters or queries              |
+| 401    | `unauthorized`    | Missing or invalid bearer token                       |
+| 403    | `forbidden`       | The caller cannot access the object                   |
+| 404    | `not_found`       | No such object                                        |
+| 500    | `internal`        | Misconfigured gateway                                 |
+| 501    | `not_implemented` | The operation is not supported by the store           |
+| 502    | `bad_gateway`     | The store (e.g. Prometheus) failed or is unreachable  |
+| 504    | `gateway_timeout` | The store did not respond in time                     |
+";
+
+/// Registers the handler of a route.
+pub type Handler = fn(::actix_web::Route) -> ::actix_web::Route;
+
+/// The routes of the gateway, both registered and described from here.
+pub fn routes() -> Vec<Route<Handler>> {
+    let routes: Vec<Route<Handler>> = vec![
+        Route {
+            method: "get",
+            path: "/",
+            handler: |route| route.to(crate::get_metric),
+            document: |op| {
+                op.summary("Get the latest location of an object")
+                    .query::<DataRef>()
+                    .response::<Location>(200, "The latest location")
+                    .errors(&[400, 401, 403, 404, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/objects",
+            handler: |route| route.to(crate::list_objects),
+            document: |op| {
+                op.summary("List the objects with their latest locations")
+                    .query::<crate::ObjectsQuery>()
+                    .response::<ObjectList>(200, "A page of the objects")
+                    .errors(&[400, 401, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/history",
+            handler: |route| route.to(crate::history::get_history),
+            document: |mut op| {
+                let trajectory = op.schema::<Vec<TimedLocation>>();
+                op.summary("Get the trajectory of an object")
+                    .query::<crate::history::HistoryQuery>()
+                    .response_with(
+                        200,
+                        "The trajectory",
+                        &[
+                            ("application/json", trajectory),
+                            ("application/geo+json", json!({ "type": "object" })),
+                            ("application/gpx+xml", json!({ "type": "string" })),
+                        ],
+                    )
+                    .errors(&[400, 401, 403, 404, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/geofences",
+            handler: |route| route.to(crate::geofence::list_geofences),
+            document: |op| {
+                op.summary("List the geofences")
+                    .response::<Vec<Geofence>>(200, "The geofences")
+                    .errors(&[401])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/geofences/membership",
+            handler: |route| route.to(crate::geofence::get_membership),
+            document: |op| {
+                op.summary("Get the geofences which each object is in")
+                    .query::<DataRefFilter>()
+                    .response::<Vec<GeofenceMembership>>(200, "The geofences of the objects")
+                    .errors(&[400, 401, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/metrics",
+            handler: |route| route.to(crate::geofence::get_metrics),
+            document: |op| {
+                op.summary("Export the geofence membership as Prometheus metrics")
+                    .description(
+                        "Authenticated and filtered like the other routes: \
+                        only the objects visible to the caller are exported, \
+                        as of the privacy rules of the caller. \
+                        The scraper should be given a bearer token of its own, e.g. an API key, \
+                        allowed to get the objects to export.",
+                    )
+                    .response_with(
+                        200,
+                        "The metrics",
+                        &[("text/plain", json!({ "type": "string" }))],
+                    )
+                    .errors(&[400, 401, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/nearby",
+            handler: |route| route.to(crate::proximity::get_nearby),
+            document: |op| {
+                op.summary("Find the objects near a point or another object")
+                    .description("Sorted by the distance, nearest first.")
+                    .query::<crate::proximity::NearbyQuery>()
+                    .response::<Vec<NearbyObject>>(200, "The nearby objects")
+                    .errors(&[400, 401, 403, 404, 501, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/stream",
+            handler: |route| route.to(crate::stream::stream_sse),
+            document: |op| {
+                op.summary("Stream the location updates as Server-Sent Events")
+                    .description(
+                        "Sends `location` events of `TimedLocationData`, \
+                        starting with the current locations. \
+                        Sends a `lagged` event of `StreamLagged` if some updates are missed, \
+                        followed by the current locations. \
+                        Subscribes the given `objects` only if no filter is given, \
+                        like a `Subscription`.",
+                    )
+                    .query::<crate::stream::SubscriptionQuery>()
+                    .response_with(
+                        200,
+                        "The location updates",
+                        &[("text/event-stream", json!({ "type": "string" }))],
+                    )
+                    .errors(&[400, 401, 502, 504])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/ws",
+            handler: |route| route.to(crate::stream::stream_ws),
+            document: |op| {
+                op.summary("Stream the location updates over a WebSocket")
+                    .description(
+                        "Sends `TimedLocationData` as JSON text messages, \
+                        starting with the current locations. \
+                        Sends `StreamLagged` if some updates are missed, \
+                        followed by the current locations. \
+                        Send a `Subscription` as a JSON text message to replace the subscription. \
+                        The `footprint.v1` subprotocol should be requested. \
+                        As browsers cannot set the `Authorization` header on WebSockets, \
+                        the bearer token can be given as another subprotocol, \
+                        `base64url.bearer.footprint.ulagbulag.io.<base64url-encoded token>`.",
+                    )
+                    .query::<crate::stream::SubscriptionQuery>()
+                    .response_with(101, "Switching to the WebSocket protocol", &[])
+                    .errors(&[400, 401])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/health",
+            handler: |route| route.to(crate::health),
+            document: |op| {
+                op.summary("Health check")
+                    .response_with(200, "Healthy", &[])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/openapi.json",
+            handler: |route| route.to(get_openapi),
+            document: |op| {
+                op.summary("Get this document")
+                    .response_with(200, "The OpenAPI document", &[])
+            },
+        },
+    ];
+
+    #[cfg(feature = "put")]
+    let routes = {
+        let mut routes = routes;
+        routes.push(Route {
+            method: "put",
+            path: "/",
+            handler: |route| route.to(crate::put),
+            document: |op| {
+                op.summary("Store the current location of an object")
+                    .description(
+                        "If authorized by the Kubernetes RBAC, the object should exist in the cluster.",
+                    )
+                    .body::<::footprint_api::LocationData>()
+                    .response_with(200, "Stored", &[])
+                    .errors(&[400, 401, 403, 404, 501, 502, 504])
+            },
+        });
+        routes
+    };
+
+    routes
+}
+
+/// Registers the handlers of [`routes`].
+pub fn configure(config: &mut ServiceConfig) {
+    for route in routes() {
+        let method = Method::from_bytes(route.method.to_uppercase().as_bytes())
+            .expect("routes should have valid methods");
+        config.service(
+            web::resource(route.path)
+                .guard(guard::Method(method))
+                .route((route.handler)(web::route())),
+        );
+    }
+}
+
+pub fn document() -> Value {
+    OpenApi::new("Footprint Gateway", env!("CARGO_PKG_VERSION"))
+        .description(DESCRIPTION)
+        .routes(&routes())
+        .with_schema::<StreamLagged>()
+        .with_schema::<Subscription>()
+        .with_schema::<TimedLocationData>()
+        .build()
+}
+
+pub async fn get_openapi() -> impl Responder {
+    static DOCUMENT: OnceLock<Value> = OnceLock::new();
+
+    HttpResponse::Ok().json(DOCUMENT.get_or_init(document))
+}
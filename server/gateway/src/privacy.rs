@@ -0,0 +1,760 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    f64::consts::{FRAC_1_SQRT_2, PI},
+    fs,
+    future::ready,
+    path::Path,
+    sync::Arc,
+};
+
+use actix_web::web::Data;
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use footprint_api::{
+    Coordinates, DataRef, DataRefFilter, Geofence, GeofenceShape, GlobalLocation, LocalLocation,
+    Location, Motion, TimedLocation, EARTH_RADIUS_M,
+};
+use footprint_client::{DataRefMatcher, LocationStore};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+
+use crate::{access::MAX_CONCURRENT_CHECKS, auth::Identity, geofence::Geofences};
+
+/// Coarsens, delays or hides the locations per object and caller, loaded once from a JSON file.
+///
+/// The first rule matching both of the object and the caller is applied,
+/// and the exact locations are exposed if none.
+pub struct PrivacyPolicy {
+    rules: Vec<Rule>,
+    /// The global geofences to snap the locations into
+    zones: Vec<Geofence>,
+    #[cfg(feature = "kubernetes")]
+    consents: Option<self::kubernetes::Consents>,
+}
+
+#[derive(Deserialize)]
+struct PrivacyPolicyFile {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Deserialize)]
+struct RuleConfig {
+    /// Regular expressions (fully anchored) on the objects, e.g. `{"kind": "users\\..*"}`
+    #[serde(default)]
+    objects: DataRefFilter,
+    /// The callers to be applied, e.g. the privileged ones; everyone if both are empty
+    #[serde(default)]
+    users: Vec<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+    /// Snaps the locations to the grid of the size
+    #[serde(default)]
+    grid_m: Option<f64>,
+    /// Snaps the locations to the center of the zone (global geofence) they are in,
+    /// hiding the ones out of the zones unless `grid_m` is given
+    #[serde(default)]
+    zones: bool,
+    /// Exposes the locations of the time before
+    #[serde(default)]
+    delay_sec: Option<f64>,
+    /// Hides the objects without the consent annotation, `footprint.ulagbulag.io/consent: "true"`
+    #[serde(default)]
+    consent: bool,
+}
+
+struct Rule {
+    objects: DataRefMatcher,
+    users: Vec<String>,
+    groups: Vec<String>,
+    grid_m: Option<f64>,
+    zones: bool,
+    delay: Option<Duration>,
+    consent: bool,
+}
+
+impl Rule {
+    fn new(rule: RuleConfig) -> Result<Self> {
+        if rule
+            .grid_m
+            .is_some_and(|grid_m| grid_m.is_nan() || grid_m <= 0.0)
+        {
+            bail!("the grid of the privacy rule should be positive");
+        }
+        if rule.delay_sec.is_some_and(|sec| sec.is_nan() || sec < 0.0) {
+            bail!("the delay of the privacy rule should not be negative");
+        }
+        Ok(Self {
+            objects: DataRefMatcher::new(&rule.objects)
+                .map_err(|error| anyhow!("invalid objects of the privacy rule: {error}"))?,
+            users: rule.users,
+            groups: rule.groups,
+            grid_m: rule.grid_m,
+            zones: rule.zones,
+            delay: rule
+                .delay_sec
+                .map(|sec| Duration::milliseconds((sec * 1000.0) as i64)),
+            consent: rule.consent,
+        })
+    }
+
+    fn is_subject(&self, identity: Option<&Identity>) -> bool {
+        (self.users.is_empty() && self.groups.is_empty())
+            || identity.is_some_and(|identity| {
+                self.users.contains(&identity.user)
+                    || self
+                        .groups
+                        .iter()
+                        .any(|group| identity.groups.contains(group))
+            })
+    }
+
+    fn is_match(&self, identity: Option<&Identity>, data: &DataRef) -> bool {
+        self.is_subject(identity) && self.objects.is_match(data)
+    }
+
+    /// Returns the coarsened location, or `None` if it should be hidden.
+    fn coarsen(&self, zones: &[Geofence], mut location: Location) -> Option<Location> {
+        if !self.zones && self.grid_m.is_none() {
+            return Some(location);
+        }
+        // the motion reveals the exact movements
+        location.motion = Motion::default();
+
+        if self.zones {
+            if let Some(zone) = zones.iter().find(|zone| zone.contains(&location)) {
+                location.global = zone_center(zone);
+                location.local = LocalLocation::default();
+                return Some(location);
+            }
+        }
+
+        let grid_m = self.grid_m?;
+        let error_m = grid_m * FRAC_1_SQRT_2;
+        let snap = |value: f64, step: f64| (value / step).round() * step;
+
+        let north = grid_m / (EARTH_RADIUS_M * PI / 180.0);
+        let latitude = snap(location.global.latitude, north);
+        // keep the cells square, except near the poles
+        let east = north / latitude.to_radians().cos().max(f64::EPSILON);
+        location.global = GlobalLocation {
+            error_m: location.global.error_m + error_m,
+            latitude,
+            longitude: snap(location.global.longitude, east),
+        };
+        location.local = LocalLocation {
+            x: snap(location.local.x, grid_m),
+            y: snap(location.local.y, grid_m),
+            error_m: location.local.error_m + error_m,
+        };
+        Some(location)
+    }
+}
+
+/// Returns the center of the zone, with the error covering it.
+fn zone_center(zone: &Geofence) -> GlobalLocation {
+    let point = |[longitude, latitude]: [f64; 2]| GlobalLocation {
+        error_m: 0.0,
+        latitude,
+        longitude,
+    };
+
+    match &zone.shape {
+        GeofenceShape::Circle { center, radius_m } => GlobalLocation {
+            error_m: *radius_m,
+            ..point(*center)
+        },
+        GeofenceShape::Polygon { points } => {
+            let n = points.len() as f64;
+            let center = point([
+                points.iter().map(|[x, _]| x).sum::<f64>() / n,
+                points.iter().map(|[_, y]| y).sum::<f64>() / n,
+            ]);
+            GlobalLocation {
+                error_m: points
+                    .iter()
+                    .map(|&p| center.distance_m(&point(p)))
+                    .fold(0.0, f64::max),
+                ..center
+            }
+        }
+    }
+}
+
+impl PrivacyPolicy {
+    pub async fn load(path: impl AsRef<Path>, geofences: &Geofences) -> Result<Self> {
+        let path = path.as_ref();
+        let PrivacyPolicyFile { rules } = fs::read(path)
+            .map_err(|error| anyhow!("failed to read the privacy policy: {path:?}: {error}"))
+            .and_then(|file| {
+                ::serde_json::from_slice(&file).map_err(|error| {
+                    anyhow!("failed to parse the privacy policy: {path:?}: {error}")
+                })
+            })?;
+
+        let rules = rules
+            .into_iter()
+            .map(Rule::new)
+            .collect::<Result<Vec<_>>>()?;
+
+        let zones = geofences
+            .iter()
+            .filter(|geofence| geofence.coordinates == Coordinates::Global)
+            .cloned()
+            .collect();
+
+        let requires_consent = rules.iter().any(|rule| rule.consent);
+        #[cfg(feature = "kubernetes")]
+        let consents = if requires_consent {
+            Some(self::kubernetes::Consents::try_default().await?)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "kubernetes"))]
+        if requires_consent {
+            bail!("the consents require the kubernetes feature");
+        }
+
+        Ok(Self {
+            rules,
+            zones,
+            #[cfg(feature = "kubernetes")]
+            consents,
+        })
+    }
+
+    fn rule(&self, identity: Option<&Identity>, data: &DataRef) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.is_match(identity, data))
+    }
+
+    /// Returns the distinct delays of the rules, for the locations shared by the callers.
+    pub fn delays(&self) -> BTreeSet<Duration> {
+        self.rules.iter().filter_map(|rule| rule.delay).collect()
+    }
+
+    /// Returns the distinct delays of the rules which may be applied to the caller.
+    fn delays_of(&self, identity: Option<&Identity>) -> BTreeSet<Duration> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.is_subject(identity))
+            .filter_map(|rule| rule.delay)
+            .collect()
+    }
+
+    async fn is_consented(&self, rule: &Rule, data: &DataRef) -> bool {
+        if !rule.consent {
+            return true;
+        }
+
+        #[cfg(feature = "kubernetes")]
+        if let Some(consents) = &self.consents {
+            return consents.is_consented(data).await;
+        }
+
+        #[cfg(not(feature = "kubernetes"))]
+        let _ = data;
+        false
+    }
+}
+
+/// The locations of the objects as of each delay before a time, to be shared by the callers.
+pub type DelayedLocations = BTreeMap<Duration, BTreeMap<DataRef, TimedLocation>>;
+
+/// Lists the locations of the objects as of each delay before `time`, with a query per delay.
+pub async fn list_delayed(
+    store: &dyn LocationStore,
+    filter: &DataRefFilter,
+    delays: impl IntoIterator<Item = Duration>,
+    time: DateTime<Utc>,
+) -> ::footprint_client::Result<DelayedLocations> {
+    let mut delayed = DelayedLocations::default();
+    for delay in delays {
+        let locations = store.list_timed_at(filter, time - delay).await?;
+        delayed.insert(delay, locations);
+    }
+    Ok(delayed)
+}
+
+/// A view of the store for a caller, applying the [`PrivacyPolicy`] on the locations.
+pub struct PrivateStore {
+    inner: Arc<dyn LocationStore>,
+    policy: Option<(Data<PrivacyPolicy>, Option<Identity>)>,
+}
+
+impl PrivateStore {
+    /// Looks back as long as the staleness of Prometheus, to find the delayed locations.
+    const LOOKBACK: ::std::time::Duration = ::std::time::Duration::from_secs(300);
+
+    pub fn new(
+        inner: Arc<dyn LocationStore>,
+        policy: Option<Data<PrivacyPolicy>>,
+        identity: Option<Identity>,
+    ) -> Self {
+        Self {
+            inner,
+            policy: policy.map(|policy| (policy, identity)),
+        }
+    }
+
+    /// Lists the locations of the objects as of the delays which may be applied to the caller.
+    async fn list_delayed(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> ::footprint_client::Result<DelayedLocations> {
+        match &self.policy {
+            Some((policy, identity)) => {
+                let delays = policy.delays_of(identity.as_ref());
+                list_delayed(&*self.inner, filter, delays, time).await
+            }
+            None => Ok(DelayedLocations::default()),
+        }
+    }
+
+    /// Protects the locations of the objects, reading their consents concurrently.
+    async fn protect_all(
+        &self,
+        objects: BTreeMap<DataRef, TimedLocation>,
+        delayed: &DelayedLocations,
+    ) -> ::footprint_client::Result<BTreeMap<DataRef, TimedLocation>> {
+        stream::iter(objects)
+            .map(|(data, location)| async move {
+                let location = self.protect(&data, location, delayed).await?;
+                Ok(location.map(|location| (data, location)))
+            })
+            .buffer_unordered(MAX_CONCURRENT_CHECKS)
+            .try_filter_map(|object| ready(Ok(object)))
+            .try_collect()
+            .await
+    }
+
+    fn rule(&self, data: &DataRef) -> Option<(&PrivacyPolicy, &Rule)> {
+        let (policy, identity) = self.policy.as_ref()?;
+        let policy: &PrivacyPolicy = policy;
+        policy
+            .rule(identity.as_ref(), data)
+            .map(|rule| (policy, rule))
+    }
+
+    /// Returns the location to be exposed, or `None` if it should be hidden.
+    ///
+    /// The delayed locations are taken from `delayed` if listed, or looked up otherwise.
+    pub async fn protect(
+        &self,
+        data: &DataRef,
+        location: TimedLocation,
+        delayed: &DelayedLocations,
+    ) -> ::footprint_client::Result<Option<TimedLocation>> {
+        let Some((policy, rule)) = self.rule(data) else {
+            return Ok(Some(location));
+        };
+        if !policy.is_consented(rule, data).await {
+            return Ok(None);
+        }
+
+        let location = match rule.delay {
+            Some(delay) => {
+                let location = match delayed.get(&delay) {
+                    Some(locations) => locations.get(data).copied(),
+                    None => self.delayed(data, delay).await?,
+                };
+                match location {
+                    Some(location) => location,
+                    None => return Ok(None),
+                }
+            }
+            None => location,
+        };
+        Ok(rule
+            .coarsen(&policy.zones, location.location)
+            .map(|coarsened| TimedLocation {
+                timestamp: location.timestamp,
+                location: coarsened,
+            }))
+    }
+
+    async fn delayed(
+        &self,
+        data: &DataRef,
+        delay: Duration,
+    ) -> ::footprint_client::Result<Option<TimedLocation>> {
+        let end = Utc::now() - delay;
+        let lookback = Duration::from_std(Self::LOOKBACK).unwrap_or_default();
+        Ok(self
+            .inner
+            .history(data, end - lookback, end, Self::LOOKBACK)
+            .await?
+            .pop())
+    }
+}
+
+#[async_trait]
+impl LocationStore for PrivateStore {
+    async fn get(&self, data: &DataRef) -> ::footprint_client::Result<Option<Location>> {
+        let Some((policy, rule)) = self.rule(data) else {
+            return self.inner.get(data).await;
+        };
+        if !policy.is_consented(rule, data).await {
+            return Ok(None);
+        }
+
+        let location = match rule.delay {
+            Some(delay) => self
+                .delayed(data, delay)
+                .await?
+                .map(|location| location.location),
+            None => self.inner.get(data).await?,
+        };
+        Ok(location.and_then(|location| rule.coarsen(&policy.zones, location)))
+    }
+
+    fn has_local_coordinates(&self) -> bool {
+        self.inner.has_local_coordinates()
+    }
+
+    async fn list_timed(
+        &self,
+        filter: &DataRefFilter,
+    ) -> ::footprint_client::Result<BTreeMap<DataRef, TimedLocation>> {
+        let objects = self.inner.list_timed(filter).await?;
+        if self.policy.is_none() {
+            return Ok(objects);
+        }
+
+        // a query per delay, rather than a history query per object
+        let delayed = self.list_delayed(filter, Utc::now()).await?;
+        self.protect_all(objects, &delayed).await
+    }
+
+    async fn list_timed_at(
+        &self,
+        filter: &DataRefFilter,
+        time: DateTime<Utc>,
+    ) -> ::footprint_client::Result<BTreeMap<DataRef, TimedLocation>> {
+        let objects = self.inner.list_timed_at(filter, time).await?;
+        if self.policy.is_none() {
+            return Ok(objects);
+        }
+
+        let delayed = self.list_delayed(filter, time).await?;
+        self.protect_all(objects, &delayed).await
+    }
+
+    async fn history(
+        &self,
+        data: &DataRef,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: ::std::time::Duration,
+    ) -> ::footprint_client::Result<Vec<TimedLocation>> {
+        let Some((policy, rule)) = self.rule(data) else {
+            return self.inner.history(data, start, end, step).await;
+        };
+        if !policy.is_consented(rule, data).await {
+            return Ok(Vec::default());
+        }
+
+        let end = match rule.delay {
+            Some(delay) => end.min(Utc::now() - delay),
+            None => end,
+        };
+        Ok(self
+            .inner
+            .history(data, start, end, step)
+            .await?
+            .into_iter()
+            .filter_map(|location| {
+                rule.coarsen(&policy.zones, location.location)
+                    .map(|coarsened| TimedLocation {
+                        timestamp: location.timestamp,
+                        location: coarsened,
+                    })
+            })
+            .collect())
+    }
+
+    async fn put(
+        &self,
+        data: &DataRef,
+        location: &TimedLocation,
+    ) -> ::footprint_client::Result<()> {
+        self.inner.put(data, location).await
+    }
+}
+
+#[cfg(feature = "kubernetes")]
+mod kubernetes {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use footprint_api::DataRef;
+    use kube::{Client, ResourceExt};
+
+    use crate::{
+        access::kubernetes::{get_object, Cache},
+        env::infer_secs,
+    };
+
+    pub const ANNOTATION_CONSENT: &str = "footprint.ulagbulag.io/consent";
+
+    /// Reads the consents from the annotations of the referenced objects, caching them for a while.
+    pub struct Consents {
+        client: Client,
+        cache_ttl: Duration,
+        cache: Cache<DataRef, bool>,
+    }
+
+    impl Consents {
+        pub async fn try_default() -> Result<Self> {
+            Ok(Self {
+                client: Client::try_default().await?,
+                cache_ttl: infer_secs("FOOTPRINT_PRIVACY_CONSENT_CACHE_SEC")?
+                    .unwrap_or(Duration::from_secs(30)),
+                cache: Cache::default(),
+            })
+        }
+
+        /// Returns whether the owner of the object has consented, hiding it if unknown.
+        pub async fn is_consented(&self, data: &DataRef) -> bool {
+            if let Some(consented) = self.cache.get(data, self.cache_ttl) {
+                return consented;
+            }
+
+            let consented = match get_object(&self.client, data).await {
+                Ok(object) => object.is_some_and(|object| {
+                    object
+                        .annotations()
+                        .get(ANNOTATION_CONSENT)
+                        .is_some_and(|value| value == "true")
+                }),
+                Err(error) => {
+                    eprintln!(
+                        "failed to read the consent of {}/{}: {error}",
+                        data.kind, data.name,
+                    );
+                    return false;
+                }
+            };
+
+            self.cache.insert(data.clone(), consented);
+            consented
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use footprint_api::GeofenceShape;
+    use footprint_client::MemoryStore;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn policy(rules: Value, zones: Vec<Geofence>) -> Data<PrivacyPolicy> {
+        let rules: Vec<RuleConfig> = ::serde_json::from_value(rules).unwrap();
+        Data::new(PrivacyPolicy {
+            rules: rules
+                .into_iter()
+                .map(Rule::new)
+                .collect::<Result<_>>()
+                .unwrap(),
+            zones,
+            #[cfg(feature = "kubernetes")]
+            consents: None,
+        })
+    }
+
+    fn rule(rule: Value) -> Rule {
+        Rule::new(::serde_json::from_value(rule).unwrap()).unwrap()
+    }
+
+    fn identity(user: &str, groups: &[&str]) -> Identity {
+        Identity {
+            user: user.into(),
+            groups: groups.iter().map(|&group| group.into()).collect(),
+        }
+    }
+
+    fn data(namespace: &str) -> DataRef {
+        DataRef {
+            kind: "user".into(),
+            name: "a".into(),
+            namespace: Some(namespace.into()),
+        }
+    }
+
+    fn location(latitude: f64, longitude: f64) -> Location {
+        Location {
+            global: GlobalLocation {
+                error_m: 1.0,
+                latitude,
+                longitude,
+            },
+            local: LocalLocation {
+                x: 149.0,
+                y: 49.0,
+                error_m: 1.0,
+            },
+            motion: Motion {
+                speed_mps: Some(1.5),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn ago(secs: i64) -> DateTime<Utc> {
+        Utc::now() - Duration::seconds(secs)
+    }
+
+    /// A square zone of about 1 km around `(127.005, 37.005)`.
+    fn zone() -> Geofence {
+        Geofence {
+            name: "campus".into(),
+            labels: Default::default(),
+            coordinates: Coordinates::Global,
+            shape: GeofenceShape::Polygon {
+                points: vec![
+                    [127.0, 37.0],
+                    [127.01, 37.0],
+                    [127.01, 37.01],
+                    [127.0, 37.01],
+                ],
+            },
+            dwell_sec: None,
+            hysteresis_m: None,
+        }
+    }
+
+    async fn store(objects: &[(DataRef, DateTime<Utc>, Location)]) -> Arc<dyn LocationStore> {
+        let store = MemoryStore::default();
+        for (data, timestamp, location) in objects {
+            let location = TimedLocation {
+                timestamp: *timestamp,
+                location: *location,
+            };
+            store.put(data, &location).await.unwrap();
+        }
+        Arc::new(store)
+    }
+
+    #[test]
+    fn snap_to_grid() {
+        let rule = rule(json!({ "grid_m": 100 }));
+        let coarsened = rule.coarsen(&[], location(37.0004, 127.0004)).unwrap();
+
+        let north = 100.0 / (EARTH_RADIUS_M * PI / 180.0);
+        let cells = coarsened.global.latitude / north;
+        assert!((cells - cells.round()).abs() < 1e-6);
+        assert!((coarsened.global.latitude - 37.0004).abs() <= north / 2.0);
+        assert!((coarsened.global.error_m - (1.0 + 100.0 * FRAC_1_SQRT_2)).abs() < 1e-9);
+        assert_eq!([coarsened.local.x, coarsened.local.y], [100.0, 0.0]);
+
+        // the motion reveals the exact movements
+        assert_eq!(coarsened.motion, Motion::default());
+    }
+
+    #[test]
+    fn fall_back_to_grid_out_of_zones() {
+        let zones = [zone()];
+
+        let rule_zoned = rule(json!({ "zones": true, "grid_m": 100 }));
+        let coarsened = rule_zoned
+            .coarsen(&zones, location(37.002, 127.003))
+            .unwrap();
+        assert!((coarsened.global.latitude - 37.005).abs() < 1e-9);
+        assert!((coarsened.global.longitude - 127.005).abs() < 1e-9);
+        assert_eq!(coarsened.local, LocalLocation::default());
+        assert_eq!(coarsened.motion, Motion::default());
+
+        let coarsened = rule_zoned.coarsen(&zones, location(38.0, 128.0)).unwrap();
+        assert_eq!([coarsened.local.x, coarsened.local.y], [100.0, 0.0]);
+        assert_eq!(coarsened.motion, Motion::default());
+
+        // hidden out of the zones without the grid
+        let rule_zoned = rule(json!({ "zones": true }));
+        assert!(rule_zoned.coarsen(&zones, location(38.0, 128.0)).is_none());
+    }
+
+    #[test]
+    fn select_delays_of_caller() {
+        let policy = policy(
+            json!([
+                { "groups": ["viewer"], "delay_sec": 60 },
+                { "users": ["bob"], "delay_sec": 120 },
+                { "objects": { "namespace": "vine" }, "delay_sec": 30 },
+            ]),
+            Vec::default(),
+        );
+        let delays = |secs: &[i64]| secs.iter().copied().map(Duration::seconds).collect();
+
+        assert_eq!(policy.delays(), delays(&[30, 60, 120]));
+        assert_eq!(
+            policy.delays_of(Some(&identity("alice", &["viewer"]))),
+            delays(&[30, 60]),
+        );
+        assert_eq!(
+            policy.delays_of(Some(&identity("bob", &[]))),
+            delays(&[30, 120])
+        );
+        assert_eq!(policy.delays_of(None), delays(&[30]));
+    }
+
+    #[tokio::test]
+    async fn expose_delayed_locations() {
+        let inner = store(&[
+            (data("vine"), ago(90), location(1.0, 1.0)),
+            (data("vine"), ago(0), location(2.0, 2.0)),
+        ])
+        .await;
+        let policy = policy(json!([{ "delay_sec": 60 }]), Vec::default());
+        let store = PrivateStore::new(inner, Some(policy), None);
+
+        let objects = store.list_timed(&DataRefFilter::default()).await.unwrap();
+        assert_eq!(objects[&data("vine")].location.global.latitude, 1.0);
+        let location = store.get(&data("vine")).await.unwrap().unwrap();
+        assert_eq!(location.global.latitude, 1.0);
+    }
+
+    #[tokio::test]
+    async fn hide_without_consent() {
+        let inner = store(&[
+            (data("vine"), ago(0), location(1.0, 1.0)),
+            (data("consent"), ago(0), location(2.0, 2.0)),
+        ])
+        .await;
+        let policy = policy(
+            json!([{ "objects": { "namespace": "consent" }, "consent": true }]),
+            Vec::default(),
+        );
+        let store = PrivateStore::new(inner, Some(policy), None);
+
+        let objects = store.list_timed(&DataRefFilter::default()).await.unwrap();
+        assert_eq!(objects.keys().collect::<Vec<_>>(), [&data("vine")]);
+        assert_eq!(store.get(&data("consent")).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn exempt_privileged_callers() {
+        let inner = store(&[(data("vine"), ago(0), location(37.0004, 127.0004))]).await;
+        let policy = policy(
+            json!([
+                { "groups": ["security"] },
+                { "grid_m": 100 },
+            ]),
+            Vec::default(),
+        );
+        let get = |identity| {
+            let store = PrivateStore::new(Arc::clone(&inner), Some(policy.clone()), identity);
+            async move { store.get(&data("vine")).await.unwrap().unwrap() }
+        };
+
+        let exact = get(Some(identity("alice", &["security"]))).await;
+        assert_eq!(exact, location(37.0004, 127.0004));
+
+        for identity in [Some(identity("bob", &["viewer"])), None] {
+            let coarsened = get(identity).await;
+            assert_eq!([coarsened.local.x, coarsened.local.y], [100.0, 0.0]);
+        }
+    }
+}
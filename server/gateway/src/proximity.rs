@@ -0,0 +1,131 @@
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse,
+};
+use footprint_api::{
+    DataRef, DataRefFilter, DistanceMetric, GlobalLocation, LocalLocation, ProximityCenter,
+    ProximityQuery,
+};
+use footprint_client::LocationStore;
+use regex::escape;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    access::{Access, VERB_GET},
+    error::{ApiError, Result},
+};
+
+/// The center is either an object (`kind`, `name` and `namespace`),
+/// a global point (`latitude` and `longitude`) or a local point (`x` and `y`).
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct NearbyQuery {
+    kind: Option<String>,
+    name: Option<String>,
+    namespace: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    x: Option<f64>,
+    y: Option<f64>,
+    /// Error of the point as meters
+    #[serde(default)]
+    error_m: f64,
+    /// Distance metric from the object; defaults to `global`
+    #[serde(default)]
+    metric: DistanceMetric,
+    radius_m: f64,
+    /// Subtracts the errors of the locations from the distances
+    #[serde(default)]
+    with_error: bool,
+    /// Finds only the objects of the kind
+    target_kind: Option<String>,
+    /// Finds only the objects in the namespace
+    target_namespace: Option<String>,
+}
+
+impl TryFrom<NearbyQuery> for ProximityQuery {
+    type Error = ApiError;
+
+    fn try_from(query: NearbyQuery) -> Result<Self, Self::Error> {
+        let center = match query {
+            NearbyQuery {
+                kind: Some(kind),
+                name: Some(name),
+                namespace,
+                latitude: None,
+                longitude: None,
+                x: None,
+                y: None,
+                metric,
+                ..
+            } => ProximityCenter::Object {
+                data: DataRef {
+                    kind,
+                    name,
+                    namespace,
+                },
+                metric,
+            },
+            NearbyQuery {
+                kind: None,
+                name: None,
+                latitude: Some(latitude),
+                longitude: Some(longitude),
+                x: None,
+                y: None,
+                error_m,
+                ..
+            } => ProximityCenter::Global(GlobalLocation {
+                error_m,
+                latitude,
+                longitude,
+            }),
+            NearbyQuery {
+                kind: None,
+                name: None,
+                latitude: None,
+                longitude: None,
+                x: Some(x),
+                y: Some(y),
+                error_m,
+                ..
+            } => ProximityCenter::Local(LocalLocation { x, y, error_m }),
+            _ => {
+                return Err(ApiError::bad_request(
+                    "exactly one of an object, a global point or a local point should be given",
+                ))
+            }
+        };
+
+        if query.radius_m.is_nan() || query.radius_m < 0.0 {
+            return Err(ApiError::bad_request("radius_m should not be negative"));
+        }
+
+        Ok(Self {
+            center,
+            radius_m: query.radius_m,
+            with_error: query.with_error,
+            filter: DataRefFilter {
+                kind: query.target_kind.as_deref().map(escape),
+                name: None,
+                namespace: query.target_namespace.as_deref().map(escape),
+            },
+        })
+    }
+}
+
+/// Returns the objects within the radius from the center, sorted by the distance.
+pub async fn get_nearby(
+    access: Access,
+    store: Data<dyn LocationStore>,
+    Query(query): Query<NearbyQuery>,
+) -> Result<HttpResponse> {
+    let query = ProximityQuery::try_from(query)?;
+    if let ProximityCenter::Object { data, .. } = &query.center {
+        access.check(data, VERB_GET).await?;
+    }
+
+    let objects = access.store(store.into_inner()).nearby(&query).await?;
+    let objects = access.retain(objects, |object| &object.data).await;
+    Ok(HttpResponse::Ok().json(objects))
+}
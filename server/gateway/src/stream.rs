@@ -0,0 +1,511 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use actix_web::{
+    http::header::{HeaderValue, SEC_WEBSOCKET_PROTOCOL},
+    rt,
+    web::{Bytes, Data, Payload, Query},
+    HttpRequest, HttpResponse,
+};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use chrono::{DateTime, Utc};
+use footprint_api::{
+    DataRef, DataRefFilter, StreamLagged, Subscription, TimedLocation, TimedLocationData,
+};
+use footprint_client::{DataRefMatcher, LocationStore, Result};
+use futures::{stream, Stream, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    select,
+    sync::broadcast::{self, error::RecvError, Receiver, Sender},
+    time::sleep,
+};
+
+use crate::{
+    access::Access,
+    error::ApiError,
+    privacy::{list_delayed, DelayedLocations, PrivacyPolicy, PrivateStore},
+};
+
+/// The WebSocket subprotocol of the location streams, the only one to be accepted.
+pub const PROTOCOL: &str = "footprint.v1";
+
+/// A change of the objects in the store.
+#[derive(Debug)]
+pub enum Update {
+    Location(TimedLocationData),
+    /// The object is no longer in the store, e.g. expired
+    Removed(DataRef),
+}
+
+/// Polls the store once for all subscribers, and fans out the changed locations to them.
+pub struct Broadcaster {
+    store: Arc<dyn LocationStore>,
+    privacy: Option<Data<PrivacyPolicy>>,
+    tx: Sender<Arc<Update>>,
+    /// The delayed locations of the last poll, shared by the subscribers
+    delayed: RwLock<Arc<DelayedLocations>>,
+}
+
+impl Broadcaster {
+    pub fn spawn(
+        store: Arc<dyn LocationStore>,
+        privacy: Option<Data<PrivacyPolicy>>,
+        interval: Duration,
+    ) -> Arc<Self> {
+        let (tx, _) = broadcast::channel(4096);
+        let broadcaster = Arc::new(Self {
+            store,
+            privacy,
+            tx,
+            delayed: RwLock::default(),
+        });
+        rt::spawn(Arc::clone(&broadcaster).run(interval));
+        broadcaster
+    }
+
+    async fn run(self: Arc<Self>, interval: Duration) {
+        let mut latest = BTreeMap::default();
+        let mut latest_delayed = Arc::new(DelayedLocations::default());
+        loop {
+            sleep(interval).await;
+
+            // skip polling while nobody is listening
+            if self.tx.receiver_count() == 0 {
+                continue;
+            }
+
+            let objects = match self.store.list_timed(&DataRefFilter::default()).await {
+                Ok(objects) => objects,
+                Err(error) => {
+                    eprintln!("failed to poll the locations: {error}");
+                    continue;
+                }
+            };
+            let delayed = match self.list_delayed(Utc::now()).await {
+                Ok(delayed) => Arc::new(delayed),
+                Err(error) => {
+                    eprintln!("failed to poll the delayed locations: {error}");
+                    continue;
+                }
+            };
+            *self.delayed.write().unwrap() = Arc::clone(&delayed);
+
+            let removed: Vec<_> = latest
+                .keys()
+                .filter(|data| !objects.contains_key(*data))
+                .cloned()
+                .collect();
+            for data in removed {
+                latest.remove(&data);
+                let _ = self.tx.send(Arc::new(Update::Removed(data)));
+            }
+            for (data, location) in objects {
+                // the delayed locations may change after the object stops
+                let is_delayed_changed = delayed.iter().any(|(delay, locations)| {
+                    locations.get(&data)
+                        != latest_delayed
+                            .get(delay)
+                            .and_then(|locations| locations.get(&data))
+                });
+                if latest.get(&data) != Some(&location) || is_delayed_changed {
+                    latest.insert(data.clone(), location);
+                    let data = TimedLocationData { data, location };
+                    let _ = self.tx.send(Arc::new(Update::Location(data)));
+                }
+            }
+            latest_delayed = delayed;
+        }
+    }
+
+    /// Subscribes the changes of all objects.
+    pub fn subscribe(&self) -> Receiver<Arc<Update>> {
+        self.tx.subscribe()
+    }
+
+    /// Lists the locations of all objects as of the delays of the privacy rules, at once.
+    async fn list_delayed(&self, time: DateTime<Utc>) -> Result<DelayedLocations> {
+        match &self.privacy {
+            Some(privacy) => {
+                list_delayed(
+                    &*self.store,
+                    &DataRefFilter::default(),
+                    privacy.delays(),
+                    time,
+                )
+                .await
+            }
+            None => Ok(DelayedLocations::default()),
+        }
+    }
+
+    /// Returns the current locations of the subscribed objects.
+    async fn snapshot(
+        &self,
+        matcher: &mut SubscriptionMatcher,
+    ) -> Result<Vec<Arc<TimedLocationData>>> {
+        let objects = self.store.list_timed(&DataRefFilter::default()).await?;
+        // not to reuse the stale ones of the last poll, e.g. after nobody has been listening
+        let delayed = self.list_delayed(Utc::now()).await?;
+
+        let mut snapshot = Vec::default();
+        for (data, location) in objects {
+            let data = TimedLocationData { data, location };
+            if let Some(data) = matcher.protect(&data, &delayed).await {
+                snapshot.push(data);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Returns the updated location to be sent to the subscriber, if any.
+    async fn protect(
+        &self,
+        matcher: &mut SubscriptionMatcher,
+        update: &Update,
+    ) -> Option<Arc<TimedLocationData>> {
+        match update {
+            Update::Location(data) => {
+                let delayed = Arc::clone(&self.delayed.read().unwrap());
+                matcher.protect(data, &delayed).await
+            }
+            Update::Removed(data) => {
+                // send it again if it comes back
+                matcher.sent.remove(data);
+                None
+            }
+        }
+    }
+}
+
+struct SubscriptionMatcher {
+    access: Access,
+    store: PrivateStore,
+    objects: BTreeSet<DataRef>,
+    filter: Option<DataRefMatcher>,
+    /// The locations sent to the subscriber, not to send the same ones again
+    sent: BTreeMap<DataRef, TimedLocation>,
+}
+
+impl SubscriptionMatcher {
+    fn new(
+        access: Access,
+        broadcaster: &Broadcaster,
+        Subscription { objects, filter }: &Subscription,
+    ) -> Result<Self> {
+        Ok(Self {
+            store: access.store(Arc::clone(&broadcaster.store)),
+            access,
+            objects: objects.iter().cloned().collect(),
+            filter: filter.as_ref().map(DataRefMatcher::new).transpose()?,
+            sent: BTreeMap::default(),
+        })
+    }
+
+    /// Returns the location to be sent to the subscriber, if any.
+    async fn protect(
+        &mut self,
+        data: &TimedLocationData,
+        delayed: &DelayedLocations,
+    ) -> Option<Arc<TimedLocationData>> {
+        if !self.is_match(&data.data).await {
+            return None;
+        }
+        let location = match self.store.protect(&data.data, data.location, delayed).await {
+            Ok(Some(location)) => location,
+            Ok(None) => return None,
+            Err(error) => {
+                eprintln!(
+                    "failed to protect the location of {}/{}: {error}",
+                    data.data.kind, data.data.name,
+                );
+                return None;
+            }
+        };
+
+        if self.sent.get(&data.data) == Some(&location) {
+            return None;
+        }
+        self.sent.insert(data.data.clone(), location);
+        Some(Arc::new(TimedLocationData {
+            data: data.data.clone(),
+            location,
+        }))
+    }
+
+    async fn is_match(&self, data: &DataRef) -> bool {
+        let is_subscribed = match &self.filter {
+            Some(filter) => self.objects.contains(data) || filter.is_match(data),
+            None => self.objects.is_empty() || self.objects.contains(data),
+        };
+        is_subscribed && self.access.is_allowed(data).await
+    }
+}
+
+/// The initial [`Subscription`] of the location streams.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct SubscriptionQuery {
+    /// The objects to subscribe, as a JSON array of `DataRef`, in addition to the filter
+    objects: Option<String>,
+    #[serde(flatten)]
+    filter: DataRefFilter,
+}
+
+impl SubscriptionQuery {
+    /// Subscribes the given objects only, or every object matching the filter as well.
+    fn parse(self) -> Result<Subscription, ApiError> {
+        let Self { objects, filter } = self;
+        match objects {
+            Some(objects) => Ok(Subscription {
+                objects: ::serde_json::from_str(&objects)
+                    .map_err(|e| ApiError::bad_request(format!("invalid objects: {e}")))?,
+                filter: Some(filter).filter(|filter| *filter != DataRefFilter::default()),
+            }),
+            None => Ok(Subscription {
+                objects: Vec::default(),
+                filter: Some(filter),
+            }),
+        }
+    }
+}
+
+/// An event of the location streams.
+enum Event {
+    Location(Arc<TimedLocationData>),
+    Lagged(StreamLagged),
+}
+
+/// Streams the locations of the subscribed objects as Server-Sent Events,
+/// starting with their current locations.
+///
+/// If the subscriber falls behind, a `lagged` event is sent, followed by the current
+/// locations of the subscribed objects.
+pub async fn stream_sse(
+    access: Access,
+    broadcaster: Data<Broadcaster>,
+    Query(query): Query<SubscriptionQuery>,
+) -> crate::error::Result<HttpResponse> {
+    let subscription = query.parse()?;
+    let mut matcher = SubscriptionMatcher::new(access, &broadcaster, &subscription)?;
+
+    // subscribe first not to miss the updates while taking a snapshot
+    let rx = broadcaster.subscribe();
+    let snapshot = broadcaster.snapshot(&mut matcher).await?;
+
+    let events = stream::iter(snapshot.into_iter().map(Event::Location))
+        .chain(updates(rx, broadcaster, matcher))
+        .map(|event| match event {
+            Event::Location(data) => {
+                let data = ::serde_json::to_string(&*data)?;
+                Ok(format!("event: location\ndata: {data}\n\n"))
+            }
+            Event::Lagged(lagged) => {
+                let data = ::serde_json::to_string(&lagged)?;
+                Ok(format!("event: lagged\ndata: {data}\n\n"))
+            }
+        });
+    let keep_alive = stream::unfold((), |()| async {
+        sleep(Duration::from_secs(15)).await;
+        Some((Ok(": keep-alive\n\n".to_string()), ()))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(
+            stream::select(events, keep_alive).map(|event: ::serde_json::Result<_>| {
+                event
+                    .map(Bytes::from)
+                    .map_err(::actix_web::error::ErrorInternalServerError)
+            }),
+        ))
+}
+
+struct Updates {
+    rx: Receiver<Arc<Update>>,
+    broadcaster: Data<Broadcaster>,
+    matcher: SubscriptionMatcher,
+    pending: VecDeque<Event>,
+}
+
+fn updates(
+    rx: Receiver<Arc<Update>>,
+    broadcaster: Data<Broadcaster>,
+    matcher: SubscriptionMatcher,
+) -> impl Stream<Item = Event> {
+    let state = Updates {
+        rx,
+        broadcaster,
+        matcher,
+        pending: VecDeque::default(),
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((event, state));
+            }
+            match state.rx.recv().await {
+                Ok(update) => {
+                    if let Some(data) = state.broadcaster.protect(&mut state.matcher, &update).await
+                    {
+                        return Some((Event::Location(data), state));
+                    }
+                }
+                Err(RecvError::Lagged(lagged)) => {
+                    state
+                        .pending
+                        .push_back(Event::Lagged(StreamLagged { lagged }));
+                    match resync(&state.broadcaster, &mut state.matcher).await {
+                        Ok(snapshot) => state
+                            .pending
+                            .extend(snapshot.into_iter().map(Event::Location)),
+                        Err(error) => {
+                            eprintln!("failed to resync the stream: {error}");
+                            return None;
+                        }
+                    }
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+/// Returns the current locations of all subscribed objects, after missing some updates.
+async fn resync(
+    broadcaster: &Broadcaster,
+    matcher: &mut SubscriptionMatcher,
+) -> Result<Vec<Arc<TimedLocationData>>> {
+    matcher.sent.clear();
+    broadcaster.snapshot(matcher).await
+}
+
+/// Streams the locations of the subscribed objects over a WebSocket,
+/// starting with their current locations.
+///
+/// The client should request the [`PROTOCOL`] subprotocol, which is echoed back.
+///
+/// The subscription can be replaced anytime by sending a [`Subscription`] as a JSON text
+/// message, followed by the current locations of the newly subscribed objects.
+/// If the subscriber falls behind, a [`StreamLagged`] message is sent, followed by the
+/// current locations of the subscribed objects.
+pub async fn stream_ws(
+    request: HttpRequest,
+    body: Payload,
+    access: Access,
+    broadcaster: Data<Broadcaster>,
+    Query(query): Query<SubscriptionQuery>,
+) -> ::actix_web::Result<HttpResponse> {
+    // never echo the other ones, e.g. carrying the bearer token
+    if !is_protocol_requested(&request) {
+        return Err(ApiError::bad_request(format!(
+            "the WebSocket subprotocol {PROTOCOL:?} should be requested"
+        ))
+        .into());
+    }
+    let subscription = query.parse()?;
+    let (mut response, session, messages) = ::actix_ws::handle(&request, body)?;
+
+    // browsers reject the connection unless one of the requested subprotocols is echoed
+    response
+        .headers_mut()
+        .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(PROTOCOL));
+
+    rt::spawn(async move {
+        if let Err(error) =
+            handle_ws(access, broadcaster, subscription, session.clone(), messages).await
+        {
+            let reason = CloseReason {
+                code: CloseCode::Error,
+                description: Some(error),
+            };
+            let _ = session.close(Some(reason)).await;
+        }
+    });
+    Ok(response)
+}
+
+/// Returns whether the client has requested the [`PROTOCOL`] subprotocol.
+fn is_protocol_requested(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get_all(SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == PROTOCOL)
+}
+
+async fn handle_ws(
+    access: Access,
+    broadcaster: Data<Broadcaster>,
+    subscription: Subscription,
+    mut session: Session,
+    mut messages: MessageStream,
+) -> Result<(), String> {
+    async fn subscribe(
+        access: &Access,
+        broadcaster: &Broadcaster,
+        session: &mut Session,
+        subscription: &Subscription,
+    ) -> Result<SubscriptionMatcher, String> {
+        let mut matcher = SubscriptionMatcher::new(access.clone(), broadcaster, subscription)
+            .map_err(|e| e.to_string())?;
+        for data in broadcaster
+            .snapshot(&mut matcher)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            send(session, &*data).await?;
+        }
+        Ok(matcher)
+    }
+
+    async fn send(session: &mut Session, data: &impl Serialize) -> Result<(), String> {
+        let data = ::serde_json::to_string(data).map_err(|e| e.to_string())?;
+        session.text(data).await.map_err(|e| e.to_string())
+    }
+
+    let mut rx = broadcaster.subscribe();
+    let mut matcher = subscribe(&access, &broadcaster, &mut session, &subscription).await?;
+    loop {
+        select! {
+            message = messages.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let subscription = ::serde_json::from_str(&text).map_err(|e| e.to_string())?;
+                    matcher = subscribe(&access, &broadcaster, &mut session, &subscription).await?;
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    session.pong(&bytes).await.map_err(|e| e.to_string())?;
+                }
+                Some(Ok(Message::Close(reason))) => {
+                    let _ = session.close(reason).await;
+                    return Ok(());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.to_string()),
+                None => return Ok(()),
+            },
+            update = rx.recv() => match update {
+                Ok(update) => {
+                    if let Some(data) = broadcaster.protect(&mut matcher, &update).await {
+                        send(&mut session, &*data).await?;
+                    }
+                }
+                Err(RecvError::Lagged(lagged)) => {
+                    send(&mut session, &StreamLagged { lagged }).await?;
+                    for data in resync(&broadcaster, &mut matcher)
+                        .await
+                        .map_err(|e| e.to_string())?
+                    {
+                        send(&mut session, &*data).await?;
+                    }
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
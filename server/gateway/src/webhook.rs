@@ -0,0 +1,329 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::rt;
+use anyhow::{anyhow, bail, Result};
+use ark_core::env::infer;
+use footprint_api::GeofenceEvent;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, StatusCode, Url};
+use sha2::Sha256;
+use tokio::{
+    sync::mpsc::{self, error::TrySendError, Receiver, Sender},
+    time::sleep,
+};
+
+use crate::env::infer_secs;
+
+pub const HEADER_EVENT: &str = "X-Footprint-Event";
+pub const HEADER_SIGNATURE: &str = "X-Footprint-Signature";
+pub const HEADER_TIMESTAMP: &str = "X-Footprint-Timestamp";
+
+pub struct WebhookConfig {
+    pub urls: Vec<Url>,
+    /// Signs the events with HMAC-SHA256 if given
+    pub secret: Option<String>,
+    pub max_retries: u32,
+    pub queue_capacity: usize,
+    pub timeout: Duration,
+}
+
+impl WebhookConfig {
+    pub fn try_from_env() -> Result<Option<Self>> {
+        let urls = match infer::<_, String>("FOOTPRINT_WEBHOOK_URLS") {
+            Ok(urls) => urls
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(|url| {
+                    url.parse()
+                        .map_err(|error| anyhow!("invalid webhook url: {url}: {error}"))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            Err(_) => return Ok(None),
+        };
+        if urls.is_empty() {
+            return Ok(None);
+        }
+
+        let timeout =
+            infer_secs("FOOTPRINT_WEBHOOK_TIMEOUT_SEC")?.unwrap_or(Duration::from_secs(10));
+        if timeout.is_zero() {
+            bail!("FOOTPRINT_WEBHOOK_TIMEOUT_SEC should be positive");
+        }
+
+        Ok(Some(Self {
+            urls,
+            secret: infer("FOOTPRINT_WEBHOOK_SECRET").ok(),
+            max_retries: infer("FOOTPRINT_WEBHOOK_MAX_RETRIES").unwrap_or(5),
+            queue_capacity: infer("FOOTPRINT_WEBHOOK_QUEUE_CAPACITY").unwrap_or(1024),
+            timeout,
+        }))
+    }
+}
+
+/// Delivers the events to each webhook in order, retrying the failed ones.
+pub struct Webhooks {
+    senders: Vec<(Url, Sender<GeofenceEvent>)>,
+}
+
+impl Webhooks {
+    pub fn spawn(config: WebhookConfig) -> Result<Self> {
+        let client = Client::builder().timeout(config.timeout).build()?;
+
+        let senders = config
+            .urls
+            .into_iter()
+            .map(|url| {
+                let (tx, rx) = mpsc::channel(config.queue_capacity);
+                let webhook = Webhook {
+                    client: client.clone(),
+                    max_retries: config.max_retries,
+                    secret: config.secret.clone(),
+                    url: url.clone(),
+                };
+                rt::spawn(webhook.run(rx));
+                (url, tx)
+            })
+            .collect();
+        Ok(Self { senders })
+    }
+
+    pub fn send(&self, event: &GeofenceEvent) {
+        for (url, tx) in &self.senders {
+            match tx.try_send(event.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    eprintln!("dropping a geofence event: the webhook queue is full: {url}");
+                }
+                Err(TrySendError::Closed(_)) => {
+                    eprintln!("dropping a geofence event: the webhook is closed: {url}");
+                }
+            }
+        }
+    }
+}
+
+struct Webhook {
+    client: Client,
+    max_retries: u32,
+    secret: Option<String>,
+    url: Url,
+}
+
+impl Webhook {
+    async fn run(self, mut rx: Receiver<GeofenceEvent>) {
+        while let Some(event) = rx.recv().await {
+            if let Err(error) = self.deliver(&event).await {
+                eprintln!(
+                    "failed to deliver a geofence event to {}: {error}",
+                    &self.url
+                );
+            }
+        }
+    }
+
+    async fn deliver(&self, event: &GeofenceEvent) -> Result<()> {
+        let body = ::serde_json::to_vec(event)?;
+        let kind = ::serde_json::to_value(event.kind)?;
+
+        let mut attempt = 0;
+        loop {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string();
+
+            let mut request = self
+                .client
+                .post(self.url.clone())
+                .header("Content-Type", "application/json")
+                .header(HEADER_EVENT, kind.as_str().unwrap_or_default())
+                .header(HEADER_TIMESTAMP, &timestamp);
+            if let Some(secret) = &self.secret {
+                request = request.header(HEADER_SIGNATURE, sign(secret, &timestamp, &body));
+            }
+
+            let error = match request.body(body.clone()).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let error = anyhow!("unexpected status: {status}");
+                    // the client errors would not be recovered by retrying
+                    if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(error) => error.into(),
+            };
+
+            if attempt >= self.max_retries {
+                return Err(error);
+            }
+            attempt += 1;
+            sleep(Duration::from_secs(1 << attempt.min(6))).await;
+        }
+    }
+}
+
+/// Signs `{timestamp}.{body}` as `sha256={hex}`, so that the receivers can reject
+/// both the forged and the replayed events.
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC should accept keys of any size");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", ::hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use chrono::DateTime;
+    use footprint_api::{DataRef, GeofenceEventKind, GlobalLocation, Location, TimedLocation};
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    impl Webhooks {
+        /// Returns the webhooks queueing the events into the receiver, instead of delivering them.
+        pub(crate) fn channel(capacity: usize) -> (Self, Receiver<GeofenceEvent>) {
+            let (tx, rx) = mpsc::channel(capacity);
+            let url = "http://localhost/".parse().unwrap();
+            (
+                Self {
+                    senders: vec![(url, tx)],
+                },
+                rx,
+            )
+        }
+    }
+
+    struct Request {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, key: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    /// Accepts a request, and responds with `200 OK`.
+    fn serve_once() -> (Url, oneshot::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let (tx, rx) = oneshot::channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut headers = Vec::default();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(':') else {
+                    break;
+                };
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+            let mut request = Request {
+                headers,
+                body: Vec::default(),
+            };
+            let len = request
+                .header("Content-Length")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0);
+            request.body.resize(len, 0);
+            reader.read_exact(&mut request.body).unwrap();
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let _ = tx.send(request);
+        });
+        (url, rx)
+    }
+
+    #[::actix_web::test]
+    async fn deliver_signed_events() {
+        let (url, rx) = serve_once();
+        let webhooks = Webhooks::spawn(WebhookConfig {
+            urls: vec![url],
+            secret: Some("secret".into()),
+            max_retries: 0,
+            queue_capacity: 1,
+            timeout: Duration::from_secs(5),
+        })
+        .unwrap();
+
+        let event = GeofenceEvent {
+            kind: GeofenceEventKind::Enter,
+            geofence: "room".into(),
+            labels: Default::default(),
+            data: DataRef {
+                kind: "user".into(),
+                name: "a".into(),
+                namespace: None,
+            },
+            location: TimedLocation {
+                timestamp: DateTime::UNIX_EPOCH,
+                location: Location {
+                    global: GlobalLocation {
+                        error_m: 1.0,
+                        latitude: 37.0,
+                        longitude: 127.0,
+                    },
+                    local: Default::default(),
+                    motion: Default::default(),
+                },
+            },
+            entered_at: DateTime::UNIX_EPOCH,
+        };
+        webhooks.send(&event);
+
+        let request = ::tokio::time::timeout(Duration::from_secs(10), rx)
+            .await
+            .expect("the event should be delivered")
+            .unwrap();
+        assert_eq!(request.header(HEADER_EVENT), Some("enter"));
+        assert_eq!(
+            ::serde_json::from_slice::<GeofenceEvent>(&request.body).unwrap(),
+            event,
+        );
+
+        // verify the signature as a receiver would
+        let timestamp = request.header(HEADER_TIMESTAMP).unwrap();
+        let signature = request
+            .header(HEADER_SIGNATURE)
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .and_then(|signature| ::hex::decode(signature).ok())
+            .unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(&request.body);
+        assert!(mac.verify_slice(&signature).is_ok());
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"forged").unwrap();
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(&request.body);
+        assert!(mac.verify_slice(&signature).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::{EnvVar, ResourceRequirements, SecretKeySelector};
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A tracked object, with the provider collecting its location.
+#[derive(Clone, Debug, PartialEq, CustomResource, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "footprint.ulagbulag.io",
+    version = "v1alpha1",
+    kind = "Footprint",
+    namespaced,
+    status = "FootprintStatus",
+    shortname = "fp",
+    printcolumn = r#"{
+        "name": "kind",
+        "type": "string",
+        "description": "kind of the tracked object",
+        "jsonPath": ".spec.object.kind"
+    }"#,
+    printcolumn = r#"{
+        "name": "provider",
+        "type": "string",
+        "description": "provider of the location",
+        "jsonPath": ".status.provider"
+    }"#,
+    printcolumn = r#"{
+        "name": "ready",
+        "type": "boolean",
+        "description": "whether the provider is running",
+        "jsonPath": ".status.ready"
+    }"#,
+    printcolumn = r#"{
+        "name": "updated-at",
+        "type": "date",
+        "description": "updated-at",
+        "jsonPath": ".status.lastUpdated"
+    }"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct FootprintSpec {
+    pub object: FootprintObject,
+    pub provider: FootprintProvider,
+    pub base: FootprintBase,
+    #[serde(default = "FootprintSpec::default_tick_sec")]
+    pub tick_sec: f64,
+
+    /// Overrides the image of the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Additional environment variables of the provider, e.g. `FOOTPRINT_FILTER`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<EnvVar>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+}
+
+impl FootprintSpec {
+    const fn default_tick_sec() -> f64 {
+        1.0
+    }
+}
+
+/// The object to be tracked, exported as the metric labels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FootprintObject {
+    /// e.g. `users.vine.ulagbulag.io/v1alpha1`
+    pub kind: String,
+    /// Defaults to the name of the resource
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+/// The global location of the origin of the provider.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FootprintBase {
+    pub error_m: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum FootprintProvider {
+    /// Random walks around the base, or moves along the waypoints given by
+    /// the `FOOTPRINT_WAYPOINTS` and `FOOTPRINT_WAYPOINTS_SPEED_MPS` envs
+    Dummy(DummyProvider),
+    SewioUwb(SewioUwbProvider),
+}
+
+impl FootprintProvider {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Dummy(_) => "dummy",
+            Self::SewioUwb(_) => "sewio-uwb",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DummyProvider {
+    /// Maximum distance from the base
+    pub radius: DummyVector,
+    /// Variance of each step
+    pub step_var: DummyVector,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DummyVector {
+    pub error_m: f64,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SewioUwbProvider {
+    /// e.g. `https://sewio.example.com/sensmapserver/api/tags`
+    pub url: String,
+    /// The UWB tag attached to the object
+    pub tag_id: usize,
+    pub api_key: SecretKeySelector,
+    pub scale: SewioUwbScale,
+}
+
+/// Scale of the local coordinates into the global ones.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SewioUwbScale {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FootprintStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub ready: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use ark_core::{env::infer, tracer};
+use futures::StreamExt;
+use k8s_openapi::{
+    api::{apps::v1::Deployment, core::v1::Service},
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+};
+use kube::{
+    api::{DynamicObject, Patch, PatchParams},
+    runtime::{watcher, Controller},
+    Api, Client, CustomResourceExt, ResourceExt,
+};
+
+use self::{
+    crd::Footprint,
+    reconcile::{error_policy, reconcile, service_monitor_resource, Context, FIELD_MANAGER},
+    writeback::Writeback,
+};
+
+mod crd;
+mod reconcile;
+mod writeback;
+
+#[tokio::main]
+async fn main() {
+    async fn try_main() -> Result<()> {
+        // print the CRD to be installed manually, e.g. `footprint-server-operator crd`
+        if ::std::env::args().nth(1).as_deref() == Some("crd") {
+            println!("{}", ::serde_json::to_string_pretty(&Footprint::crd())?);
+            return Ok(());
+        }
+
+        let client = Client::try_default().await?;
+        if infer("FOOTPRINT_OPERATOR_INSTALL_CRD").unwrap_or(true) {
+            let crd = Footprint::crd();
+            Api::<CustomResourceDefinition>::all(client.clone())
+                .patch(
+                    &crd.name_any(),
+                    &PatchParams::apply(FIELD_MANAGER).force(),
+                    &Patch::Apply(&crd),
+                )
+                .await?;
+        }
+
+        if let Some(writeback) = Writeback::try_from_env(client.clone())? {
+            ::tokio::spawn(writeback.run());
+        }
+
+        let ctx = Arc::new(Context {
+            client: client.clone(),
+            image: infer("FOOTPRINT_OPERATOR_IMAGE")
+                .unwrap_or_else(|_| "quay.io/ulagbulag/footprint:latest".into()),
+            service_monitor: infer("FOOTPRINT_OPERATOR_SERVICE_MONITOR").unwrap_or(true),
+        });
+
+        let config = watcher::Config::default();
+        let controller = Controller::new(Api::<Footprint>::all(client.clone()), config.clone())
+            .owns(Api::<Deployment>::all(client.clone()), config.clone())
+            .owns(Api::<Service>::all(client.clone()), config.clone());
+        let controller = if ctx.service_monitor {
+            let resource = service_monitor_resource();
+            controller.owns_with(
+                Api::<DynamicObject>::all_with(client, &resource),
+                resource,
+                config,
+            )
+        } else {
+            controller
+        };
+        controller
+            .shutdown_on_signal()
+            .run(reconcile, error_policy, ctx)
+            .for_each(|result| async move {
+                if let Err(error) = result {
+                    eprintln!("failed to reconcile: {error}");
+                }
+            })
+            .await;
+        Ok(())
+    }
+
+    tracer::init_once();
+    try_main().await.expect("running an operator")
+}
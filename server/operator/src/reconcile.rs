@@ -0,0 +1,569 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use k8s_openapi::{
+    api::{
+        apps::v1::{Deployment, DeploymentSpec},
+        core::v1::{
+            Container, ContainerPort, EnvVar, EnvVarSource, HTTPGetAction, PodSpec,
+            PodTemplateSpec, Probe, ResourceRequirements, Service, ServicePort, ServiceSpec,
+        },
+    },
+    apimachinery::pkg::{
+        api::resource::Quantity,
+        apis::meta::v1::{LabelSelector, ObjectMeta},
+        util::intstr::IntOrString,
+    },
+};
+use kube::{
+    api::{ApiResource, DynamicObject, GroupVersionKind, Patch, PatchParams},
+    runtime::controller::Action,
+    Api, Client, Resource, ResourceExt,
+};
+use serde_json::json;
+
+use crate::crd::{Footprint, FootprintProvider, FootprintStatus};
+
+pub const FIELD_MANAGER: &str = "footprint-operator";
+pub const LABEL_NAME: &str = "footprint.ulagbulag.io/name";
+
+pub type Result<T, E = Error> = ::std::result::Result<T, E>;
+
+#[derive(Debug, ::thiserror::Error)]
+pub enum Error {
+    #[error("failed to communicate with the kubernetes: {0}")]
+    Kube(#[from] ::kube::Error),
+    #[error("missing object key: {0}")]
+    MissingObjectKey(&'static str),
+}
+
+pub struct Context {
+    pub client: Client,
+    pub image: String,
+    /// Skips the service monitors if the prometheus operator is not installed
+    pub service_monitor: bool,
+}
+
+pub async fn reconcile(footprint: Arc<Footprint>, ctx: Arc<Context>) -> Result<Action> {
+    let namespace = footprint
+        .namespace()
+        .ok_or(Error::MissingObjectKey(".metadata.namespace"))?;
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+
+    let deployment = build_deployment(&footprint, &ctx.image)?;
+    let deployment = Api::<Deployment>::namespaced(ctx.client.clone(), &namespace)
+        .patch(&deployment.name_any(), &params, &Patch::Apply(&deployment))
+        .await?;
+
+    let service = build_service(&footprint)?;
+    Api::<Service>::namespaced(ctx.client.clone(), &namespace)
+        .patch(&service.name_any(), &params, &Patch::Apply(&service))
+        .await?;
+
+    if ctx.service_monitor {
+        let (resource, service_monitor) = build_service_monitor(&footprint)?;
+        Api::<DynamicObject>::namespaced_with(ctx.client.clone(), &namespace, &resource)
+            .patch(
+                &service_monitor.name_any(),
+                &params,
+                &Patch::Apply(&service_monitor),
+            )
+            .await?;
+    }
+
+    let ready = deployment
+        .status
+        .and_then(|status| status.available_replicas)
+        .unwrap_or_default()
+        > 0;
+    let status = FootprintStatus {
+        provider: Some(footprint.spec.provider.name().into()),
+        ready,
+        last_updated: Some(Utc::now()),
+    };
+    Api::<Footprint>::namespaced(ctx.client.clone(), &namespace)
+        .patch_status(
+            &footprint.name_any(),
+            &params,
+            &Patch::Apply(json!({
+                "apiVersion": Footprint::api_version(&()),
+                "kind": Footprint::kind(&()),
+                "status": status,
+            })),
+        )
+        .await?;
+
+    // poll the readiness until the provider is up
+    Ok(Action::requeue(if ready {
+        Duration::from_secs(300)
+    } else {
+        Duration::from_secs(5)
+    }))
+}
+
+pub fn error_policy(footprint: Arc<Footprint>, error: &Error, _ctx: Arc<Context>) -> Action {
+    eprintln!(
+        "failed to reconcile footprint {}/{}: {error}",
+        footprint.namespace().unwrap_or_default(),
+        footprint.name_any(),
+    );
+    Action::requeue(Duration::from_secs(30))
+}
+
+fn name(footprint: &Footprint) -> String {
+    format!("footprint-{}", footprint.name_any())
+}
+
+fn labels(footprint: &Footprint) -> BTreeMap<String, String> {
+    [
+        ("name".into(), name(footprint)),
+        (LABEL_NAME.into(), footprint.name_any()),
+    ]
+    .into()
+}
+
+fn metadata(footprint: &Footprint) -> Result<ObjectMeta> {
+    Ok(ObjectMeta {
+        name: Some(name(footprint)),
+        namespace: footprint.namespace(),
+        labels: Some(labels(footprint)),
+        // garbage-collected with the footprint
+        owner_references: Some(vec![footprint
+            .controller_owner_ref(&())
+            .ok_or(Error::MissingObjectKey(".metadata.uid"))?]),
+        ..Default::default()
+    })
+}
+
+pub fn build_env(footprint: &Footprint) -> Vec<EnvVar> {
+    fn env(name: &str, value: impl ToString) -> EnvVar {
+        EnvVar {
+            name: name.into(),
+            value: Some(value.to_string()),
+            value_from: None,
+        }
+    }
+
+    let spec = &footprint.spec;
+    let mut envs = vec![
+        env("FOOTPRINT_KIND", &spec.object.kind),
+        env(
+            "FOOTPRINT_NAME",
+            spec.object
+                .name
+                .clone()
+                .unwrap_or_else(|| footprint.name_any()),
+        ),
+        env(
+            "FOOTPRINT_NAMESPACE",
+            spec.object.namespace.as_deref().unwrap_or_default(),
+        ),
+        env("FOOTPRINT_PROVIDER", spec.provider.name()),
+        env("FOOTPRINT_BASE_ERROR_M", spec.base.error_m),
+        env("FOOTPRINT_BASE_LATITUDE", spec.base.latitude),
+        env("FOOTPRINT_BASE_LONGITUDE", spec.base.longitude),
+        env("FOOTPRINT_BASE_ROTATION", spec.base.rotation),
+        env("FOOTPRINT_TICK_SEC", spec.tick_sec),
+        env("RUST_LOG", "INFO"),
+    ];
+
+    match &spec.provider {
+        FootprintProvider::Dummy(provider) => envs.extend([
+            env("FOOTPRINT_RADIUS_ERROR_M", provider.radius.error_m),
+            env("FOOTPRINT_RADIUS_LATITUDE", provider.radius.latitude),
+            env("FOOTPRINT_RADIUS_LONGITUDE", provider.radius.longitude),
+            env("FOOTPRINT_STEP_VAR_ERROR_M", provider.step_var.error_m),
+            env("FOOTPRINT_STEP_VAR_LATITUDE", provider.step_var.latitude),
+            env("FOOTPRINT_STEP_VAR_LONGITUDE", provider.step_var.longitude),
+        ]),
+        FootprintProvider::SewioUwb(provider) => envs.extend([
+            env("FOOTPRINT_API_URL", &provider.url),
+            env("FOOTPRINT_API_ID", provider.tag_id),
+            EnvVar {
+                name: "FOOTPRINT_API_KEY".into(),
+                value: None,
+                value_from: Some(EnvVarSource {
+                    secret_key_ref: Some(provider.api_key.clone()),
+                    ..Default::default()
+                }),
+            },
+            env("FOOTPRINT_SCALE_LATITUDE", provider.scale.latitude),
+            env("FOOTPRINT_SCALE_LONGITUDE", provider.scale.longitude),
+        ]),
+    }
+
+    // the user-defined ones take precedence
+    envs.retain(|env| spec.env.iter().all(|custom| custom.name != env.name));
+    envs.extend(spec.env.iter().cloned());
+    envs
+}
+
+pub fn build_deployment(footprint: &Footprint, image: &str) -> Result<Deployment> {
+    let labels = labels(footprint);
+    let resources = footprint.spec.resources.clone().unwrap_or_else(|| {
+        fn quantities(cpu: &str, memory: &str) -> Option<BTreeMap<String, Quantity>> {
+            Some(
+                [
+                    ("cpu".into(), Quantity(cpu.into())),
+                    ("memory".into(), Quantity(memory.into())),
+                ]
+                .into(),
+            )
+        }
+
+        ResourceRequirements {
+            requests: quantities("30m", "20Mi"),
+            limits: quantities("50m", "50Mi"),
+            ..Default::default()
+        }
+    });
+
+    Ok(Deployment {
+        metadata: metadata(footprint)?,
+        spec: Some(DeploymentSpec {
+            replicas: Some(1),
+            selector: LabelSelector {
+                match_labels: Some(labels.clone()),
+                ..Default::default()
+            },
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: "footprint".into(),
+                        image: Some(footprint.spec.image.as_deref().unwrap_or(image).into()),
+                        image_pull_policy: Some("Always".into()),
+                        command: Some(vec!["footprint-server-provider".into()]),
+                        env: Some(build_env(footprint)),
+                        ports: Some(vec![ContainerPort {
+                            name: Some("http".into()),
+                            protocol: Some("TCP".into()),
+                            container_port: 80,
+                            ..Default::default()
+                        }]),
+                        liveness_probe: Some(Probe {
+                            initial_delay_seconds: Some(5),
+                            http_get: Some(HTTPGetAction {
+                                path: Some("/health".into()),
+                                port: IntOrString::Int(80),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }),
+                        resources: Some(resources),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        status: None,
+    })
+}
+
+pub fn build_service(footprint: &Footprint) -> Result<Service> {
+    Ok(Service {
+        metadata: metadata(footprint)?,
+        spec: Some(ServiceSpec {
+            type_: Some("ClusterIP".into()),
+            selector: Some(labels(footprint)),
+            ports: Some(vec![ServicePort {
+                name: Some("http".into()),
+                port: 80,
+                protocol: Some("TCP".into()),
+                target_port: Some(IntOrString::Int(80)),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        status: None,
+    })
+}
+
+/// The resource of the service monitors of the prometheus operator.
+pub fn service_monitor_resource() -> ApiResource {
+    ApiResource::from_gvk_with_plural(
+        &GroupVersionKind::gvk("monitoring.coreos.com", "v1", "ServiceMonitor"),
+        "servicemonitors",
+    )
+}
+
+pub fn build_service_monitor(footprint: &Footprint) -> Result<(ApiResource, DynamicObject)> {
+    let resource = service_monitor_resource();
+    let namespace = footprint.namespace().unwrap_or_default();
+
+    let mut service_monitor = DynamicObject::new(&name(footprint), &resource);
+    service_monitor.metadata = metadata(footprint)?;
+    service_monitor.data = json!({
+        "spec": {
+            "endpoints": [
+                {
+                    "port": "http",
+                    "path": "/metrics",
+                },
+            ],
+            "selector": {
+                "matchLabels": labels(footprint),
+            },
+            "namespaceSelector": {
+                "matchNames": [namespace],
+            },
+        },
+    });
+    Ok((resource, service_monitor))
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{Request, Response};
+    use kube::client::Body;
+    use serde_json::Value;
+    use tower_test::mock;
+
+    use super::*;
+
+    fn footprint(provider: Value) -> Footprint {
+        ::serde_json::from_value(json!({
+            "apiVersion": "footprint.ulagbulag.io/v1alpha1",
+            "kind": "Footprint",
+            "metadata": {
+                "name": "forklift-1",
+                "namespace": "default",
+                "uid": "1234",
+            },
+            "spec": {
+                "object": {
+                    "kind": "forklifts.example.com/v1",
+                },
+                "provider": provider,
+                "base": {
+                    "errorM": 0.1,
+                    "latitude": 35.0,
+                    "longitude": 126.0,
+                },
+                "env": [
+                    {
+                        "name": "RUST_LOG",
+                        "value": "DEBUG",
+                    },
+                ],
+            },
+        }))
+        .unwrap()
+    }
+
+    fn dummy() -> Footprint {
+        footprint(json!({
+            "dummy": {
+                "radius": {
+                    "errorM": 1.0,
+                    "latitude": 0.001,
+                    "longitude": 0.001,
+                },
+                "stepVar": {
+                    "errorM": 0.1,
+                    "latitude": 0.0001,
+                    "longitude": 0.0001,
+                },
+            },
+        }))
+    }
+
+    fn sewio_uwb() -> Footprint {
+        footprint(json!({
+            "sewioUwb": {
+                "url": "http://sewio/api/tags",
+                "tagId": 42,
+                "apiKey": {
+                    "name": "sewio",
+                    "key": "api-key",
+                },
+                "scale": {
+                    "latitude": 1.0,
+                    "longitude": 1.0,
+                },
+            },
+        }))
+    }
+
+    fn env_value<'a>(envs: &'a [EnvVar], name: &str) -> Option<&'a str> {
+        envs.iter()
+            .find(|env| env.name == name)
+            .and_then(|env| env.value.as_deref())
+    }
+
+    #[test]
+    fn build_env_of_dummy() {
+        let envs = build_env(&dummy());
+
+        assert_eq!(
+            env_value(&envs, "FOOTPRINT_KIND"),
+            Some("forklifts.example.com/v1")
+        );
+        // defaults to the name of the resource
+        assert_eq!(env_value(&envs, "FOOTPRINT_NAME"), Some("forklift-1"));
+        assert_eq!(env_value(&envs, "FOOTPRINT_NAMESPACE"), Some(""));
+        assert_eq!(env_value(&envs, "FOOTPRINT_PROVIDER"), Some("dummy"));
+        assert_eq!(env_value(&envs, "FOOTPRINT_RADIUS_ERROR_M"), Some("1"));
+        assert_eq!(env_value(&envs, "FOOTPRINT_TICK_SEC"), Some("1"));
+    }
+
+    #[test]
+    fn build_env_of_sewio_uwb() {
+        let envs = build_env(&sewio_uwb());
+
+        assert_eq!(env_value(&envs, "FOOTPRINT_PROVIDER"), Some("sewio-uwb"));
+        assert_eq!(env_value(&envs, "FOOTPRINT_API_ID"), Some("42"));
+        // the api key is not exposed in the spec
+        let api_key = envs
+            .iter()
+            .find(|env| env.name == "FOOTPRINT_API_KEY")
+            .unwrap();
+        assert_eq!(api_key.value, None);
+        let secret = api_key
+            .value_from
+            .as_ref()
+            .and_then(|source| source.secret_key_ref.as_ref())
+            .unwrap();
+        assert_eq!(
+            (secret.name.as_str(), secret.key.as_str()),
+            ("sewio", "api-key")
+        );
+    }
+
+    #[test]
+    fn build_env_with_overrides() {
+        let envs = build_env(&dummy());
+
+        // the user-defined ones take precedence, without duplicates
+        let logs: Vec<_> = envs.iter().filter(|env| env.name == "RUST_LOG").collect();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].value.as_deref(), Some("DEBUG"));
+    }
+
+    #[test]
+    fn build_deployment_of_provider() {
+        let footprint = dummy();
+        let deployment = build_deployment(&footprint, "img:1").unwrap();
+
+        assert_eq!(deployment.name_any(), "footprint-forklift-1");
+        assert_eq!(deployment.namespace().as_deref(), Some("default"));
+        let owner = &deployment.metadata.owner_references.as_ref().unwrap()[0];
+        assert_eq!((owner.uid.as_str(), owner.controller), ("1234", Some(true)));
+
+        let spec = deployment.spec.unwrap();
+        assert_eq!(spec.selector.match_labels, Some(labels(&footprint)));
+        let container = &spec.template.spec.unwrap().containers[0];
+        assert_eq!(container.image.as_deref(), Some("img:1"));
+        assert_eq!(container.env.as_ref(), Some(&build_env(&footprint)));
+        assert!(container.resources.is_some());
+    }
+
+    #[test]
+    fn build_deployment_with_image() {
+        let mut footprint = dummy();
+        footprint.spec.image = Some("img:2".into());
+        let deployment = build_deployment(&footprint, "img:1").unwrap();
+
+        let container = &deployment.spec.unwrap().template.spec.unwrap().containers[0];
+        assert_eq!(container.image.as_deref(), Some("img:2"));
+    }
+
+    #[test]
+    fn reject_footprint_without_uid() {
+        let mut footprint = dummy();
+        footprint.metadata.uid = None;
+        assert!(matches!(
+            build_deployment(&footprint, "img:1"),
+            Err(Error::MissingObjectKey(_)),
+        ));
+    }
+
+    #[test]
+    fn build_service_monitor_of_provider() {
+        let footprint = dummy();
+        let (resource, service_monitor) = build_service_monitor(&footprint).unwrap();
+
+        assert_eq!(resource, service_monitor_resource());
+        assert_eq!(service_monitor.name_any(), "footprint-forklift-1");
+        assert_eq!(
+            service_monitor.types.map(|types| types.kind).as_deref(),
+            Some("ServiceMonitor"),
+        );
+        assert_eq!(
+            service_monitor.data["spec"]["selector"]["matchLabels"],
+            json!(labels(&footprint)),
+        );
+        assert_eq!(
+            service_monitor.data["spec"]["namespaceSelector"]["matchNames"],
+            json!(["default"]),
+        );
+        assert_eq!(service_monitor.data["spec"]["endpoints"][0]["port"], "http");
+    }
+
+    #[tokio::test]
+    async fn reconcile_with_mocked_api_server() {
+        let (service, mut handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let ctx = Arc::new(Context {
+            client: Client::new(service, "default"),
+            image: "img:1".into(),
+            service_monitor: true,
+        });
+
+        let server = ::tokio::spawn(async move {
+            let mut requests = Vec::default();
+            for _ in 0..4 {
+                let (request, send) = handle.next_request().await.unwrap();
+                let method = request.method().to_string();
+                let path = request.uri().path().to_string();
+                let body = request.into_body().collect_bytes().await.unwrap();
+                let mut object: Value = ::serde_json::from_slice(&body).unwrap();
+
+                // echo the applied objects back, as the api server would
+                if path.ends_with("/deployments/footprint-forklift-1") {
+                    object["status"] = json!({ "availableReplicas": 1 });
+                }
+                if path.ends_with("/status") {
+                    object = ::serde_json::to_value(dummy()).unwrap();
+                }
+                send.send_response(
+                    Response::builder()
+                        .body(Body::from(::serde_json::to_vec(&object).unwrap()))
+                        .unwrap(),
+                );
+                requests.push((
+                    method,
+                    path,
+                    ::serde_json::from_slice::<Value>(&body).unwrap(),
+                ));
+            }
+            requests
+        });
+
+        let action = reconcile(Arc::new(dummy()), ctx).await.unwrap();
+        assert_eq!(action, Action::requeue(Duration::from_secs(300)));
+
+        let requests = server.await.unwrap();
+        let paths: Vec<_> = requests
+            .iter()
+            .map(|(method, path, _)| format!("{method} {path}"))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "PATCH /apis/apps/v1/namespaces/default/deployments/footprint-forklift-1",
+                "PATCH /api/v1/namespaces/default/services/footprint-forklift-1",
+                "PATCH /apis/monitoring.coreos.com/v1/namespaces/default/servicemonitors/footprint-forklift-1",
+                "PATCH /apis/footprint.ulagbulag.io/v1alpha1/namespaces/default/footprints/forklift-1/status",
+            ],
+        );
+
+        let status = &requests[3].2["status"];
+        assert_eq!(status["provider"], "dummy");
+        assert_eq!(status["ready"], true);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::BTreeMap, env, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, bail, Error, Result};
+use ark_core::env::infer;
+use footprint_api::{DataRef, Location, TimedLocation, TimedLocationData};
+use kube::{
+    api::{ApiResource, DynamicObject, Patch, PatchParams},
+    Api, Client,
+};
+use serde_json::json;
+use tokio::time::sleep;
+
+pub const ANNOTATION_LOCATION: &str = "footprint.ulagbulag.io/location";
+
+/// Where to write the locations into the referenced objects.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WritebackTarget {
+    /// As a JSON annotation, `footprint.ulagbulag.io/location`
+    Annotation,
+    /// As `.status.footprint`, which should be allowed by the schema of the object
+    Status,
+}
+
+impl FromStr for WritebackTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "annotation" => Ok(Self::Annotation),
+            "status" => Ok(Self::Status),
+            target => bail!("unknown writeback target: {target}"),
+        }
+    }
+}
+
+/// Periodically writes the latest locations back into the referenced objects.
+///
+/// The locations are read from the gateway, so that its privacy policy
+/// (coarsening, delays and consents) applies to the written ones too.
+pub struct Writeback {
+    pub client: Client,
+    pub gateway: ::footprint_client::Client,
+    /// The kinds to write back, e.g. `users.vine.ulagbulag.io/v1alpha1`
+    pub kinds: Vec<String>,
+    /// The namespaces to write back, or all namespaces if empty
+    pub namespaces: Vec<String>,
+    pub target: WritebackTarget,
+    pub interval: Duration,
+    /// The minimum distance to move before writing the location again
+    pub min_distance_m: f64,
+}
+
+impl Writeback {
+    /// Returns the writeback configured from the environment variables,
+    /// or `None` if it is not enabled.
+    pub fn try_from_env(client: Client) -> Result<Option<Self>> {
+        let target = match infer::<_, String>("FOOTPRINT_WRITEBACK")
+            .unwrap_or_else(|_| "none".into())
+            .as_str()
+        {
+            "none" => return Ok(None),
+            target => target.parse()?,
+        };
+
+        let kinds = parse_list("FOOTPRINT_WRITEBACK_KINDS");
+        if kinds.is_empty() {
+            bail!("FOOTPRINT_WRITEBACK_KINDS should be given to enable the writeback");
+        }
+
+        let interval = match env::var("FOOTPRINT_WRITEBACK_INTERVAL_SEC") {
+            Ok(_) => infer::<_, f64>("FOOTPRINT_WRITEBACK_INTERVAL_SEC")?,
+            Err(_) => 10.0,
+        };
+        let interval = Duration::try_from_secs_f64(interval).map_err(|error| {
+            anyhow!("invalid FOOTPRINT_WRITEBACK_INTERVAL_SEC: {interval}: {error}")
+        })?;
+        if interval.is_zero() {
+            bail!("FOOTPRINT_WRITEBACK_INTERVAL_SEC should be positive");
+        }
+
+        let min_distance_m = match env::var("FOOTPRINT_WRITEBACK_MIN_DISTANCE_M") {
+            Ok(_) => infer::<_, f64>("FOOTPRINT_WRITEBACK_MIN_DISTANCE_M")?,
+            Err(_) => 1.0,
+        };
+        if !min_distance_m.is_finite() || min_distance_m < 0.0 {
+            bail!("invalid FOOTPRINT_WRITEBACK_MIN_DISTANCE_M: {min_distance_m}");
+        }
+
+        Ok(Some(Self {
+            client,
+            gateway: ::footprint_client::Client::try_default()?,
+            kinds,
+            namespaces: parse_list("FOOTPRINT_WRITEBACK_NAMESPACES"),
+            target,
+            interval,
+            min_distance_m,
+        }))
+    }
+
+    pub async fn run(self) {
+        // the locations already written, not to patch the unmoved ones
+        let mut written: BTreeMap<DataRef, Location> = BTreeMap::default();
+        loop {
+            match self.list().await {
+                Ok(locations) => {
+                    written.retain(|data, _| locations.contains_key(data));
+                    for (data, location) in locations {
+                        if written.get(&data).is_some_and(|last| {
+                            !is_moved(last, &location.location, self.min_distance_m)
+                        }) {
+                            continue;
+                        }
+                        match self.write(&data, &location).await {
+                            Ok(()) => {
+                                written.insert(data, location.location);
+                            }
+                            Err(error) => eprintln!(
+                                "failed to write the location of {}/{}: {error}",
+                                data.kind, data.name,
+                            ),
+                        }
+                    }
+                }
+                Err(error) => eprintln!("failed to list the locations: {error}"),
+            }
+            sleep(self.interval).await;
+        }
+    }
+
+    async fn list(&self) -> Result<BTreeMap<DataRef, TimedLocation>> {
+        let mut locations = BTreeMap::default();
+        for kind in &self.kinds {
+            let items = if self.namespaces.is_empty() {
+                self.gateway.list_objects(kind, None).await?
+            } else {
+                let mut items = Vec::default();
+                for namespace in &self.namespaces {
+                    items.extend(self.gateway.list_objects(kind, Some(namespace)).await?);
+                }
+                items
+            };
+            locations.extend(
+                items
+                    .into_iter()
+                    .map(|TimedLocationData { data, location }| (data, location)),
+            );
+        }
+        Ok(locations)
+    }
+
+    async fn write(&self, data: &DataRef, location: &TimedLocation) -> Result<()> {
+        let (group, version, plural) = data
+            .resource()
+            .ok_or_else(|| anyhow!("not a kubernetes resource: {}", data.kind))?;
+        let resource = ApiResource {
+            group: group.into(),
+            version: version.into(),
+            api_version: if group.is_empty() {
+                version.into()
+            } else {
+                format!("{group}/{version}")
+            },
+            // not used by the merge patches
+            kind: String::default(),
+            plural: plural.into(),
+        };
+        let api = match data.namespace.as_deref().filter(|ns| !ns.is_empty()) {
+            Some(namespace) => {
+                Api::<DynamicObject>::namespaced_with(self.client.clone(), namespace, &resource)
+            }
+            None => Api::<DynamicObject>::all_with(self.client.clone(), &resource),
+        };
+
+        let params = PatchParams::default();
+        match self.target {
+            WritebackTarget::Annotation => {
+                let patch = json!({
+                    "metadata": {
+                        "annotations": {
+                            ANNOTATION_LOCATION: ::serde_json::to_string(location)?,
+                        },
+                    },
+                });
+                api.patch(&data.name, &params, &Patch::Merge(patch)).await?;
+            }
+            WritebackTarget::Status => {
+                let patch = json!({
+                    "status": {
+                        "footprint": location,
+                    },
+                });
+                api.patch_status(&data.name, &params, &Patch::Merge(patch))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(Into::into)
+        .collect()
+}
+
+/// Returns whether the coordinates have moved at least the given distance,
+/// regardless of the timestamps.
+fn is_moved(last: &Location, location: &Location, min_distance_m: f64) -> bool {
+    // the coordinates have appeared or disappeared
+    if last.global.latitude.is_nan() != location.global.latitude.is_nan()
+        || last.local.x.is_nan() != location.local.x.is_nan()
+    {
+        return true;
+    }
+    [
+        last.global.distance_m(&location.global),
+        last.local.distance_m(&location.local),
+    ]
+    .into_iter()
+    .any(|distance_m| distance_m >= min_distance_m)
+}
+
+#[cfg(test)]
+mod tests {
+    use footprint_api::{GlobalLocation, LocalLocation, Motion};
+
+    use super::*;
+
+    fn location(latitude: f64, x: f64) -> Location {
+        Location {
+            global: GlobalLocation {
+                error_m: 0.0,
+                latitude,
+                longitude: 127.0,
+            },
+            local: LocalLocation {
+                x,
+                y: 0.0,
+                error_m: 0.0,
+            },
+            motion: Motion::default(),
+        }
+    }
+
+    #[test]
+    fn compare_only_coordinates() {
+        let last = location(37.0, 1.0);
+        assert!(!is_moved(&last, &location(37.0, 1.0), 1.0));
+        // ~0.1 m to the north
+        assert!(!is_moved(&last, &location(37.000_001, 1.0), 1.0));
+        // ~11 m to the north
+        assert!(is_moved(&last, &location(37.000_1, 1.0), 1.0));
+        assert!(is_moved(&last, &location(37.0, 2.5), 1.0));
+
+        // the local coordinates have disappeared
+        assert!(is_moved(&last, &location(37.0, f64::NAN), 1.0));
+        assert!(!is_moved(
+            &location(37.0, f64::NAN),
+            &location(37.0, f64::NAN),
+            1.0,
+        ));
+    }
+}
@@ -1,29 +1,129 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::OnceLock};
 
-use actix_web::{get, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    guard,
+    http::Method,
+    web::{self, ServiceConfig},
+    App, HttpResponse, HttpServer, Responder,
+};
 use actix_web_prom::PrometheusMetricsBuilder;
 use anyhow::{anyhow, Result};
 use ark_core::{env::infer, tracer};
+use footprint_api::openapi::{OpenApi, Route};
+use serde_json::Value;
 
-#[get("/")]
 async fn index() -> impl Responder {
     HttpResponse::Ok().json("footprint")
 }
 
-#[get("/health")]
 async fn health() -> impl Responder {
     HttpResponse::Ok().json("healthy")
 }
 
 #[cfg(feature = "put")]
-#[::actix_web::put("/")]
 async fn put(
     ::actix_web::web::Json(location): ::actix_web::web::Json<::footprint_api::ObjectLocation>,
 ) -> impl Responder {
-    ::footprint_provider_api::update(location);
+    ::footprint_provider_api::update(location, ::chrono::Utc::now());
     HttpResponse::Ok().finish()
 }
 
+/// Registers the handler of a route.
+type Handler = fn(::actix_web::Route) -> ::actix_web::Route;
+
+/// The routes of the provider, both registered and described from here.
+///
+/// The `/metrics` endpoint is served by the Prometheus middleware, so it is described only.
+fn routes() -> Vec<Route<Option<Handler>>> {
+    let routes: Vec<Route<Option<Handler>>> = vec![
+        Route {
+            method: "get",
+            path: "/",
+            handler: Some(|route| route.to(index)),
+            document: |mut op| {
+                let name = op.schema::<String>();
+                op.summary("Get the name of the service").response_with(
+                    200,
+                    "The name",
+                    &[("application/json", name)],
+                )
+            },
+        },
+        Route {
+            method: "get",
+            path: "/health",
+            handler: Some(|route| route.to(health)),
+            document: |op| {
+                op.summary("Health check")
+                    .response_with(200, "Healthy", &[])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/metrics",
+            handler: None,
+            document: |mut op| {
+                let metrics = op.schema::<String>();
+                op.summary("Get the latest location as Prometheus metrics")
+                    .response_with(200, "The metrics", &[("text/plain", metrics)])
+            },
+        },
+        Route {
+            method: "get",
+            path: "/openapi.json",
+            handler: Some(|route| route.to(openapi)),
+            document: |op| {
+                op.summary("Get this document")
+                    .response_with(200, "The OpenAPI document", &[])
+            },
+        },
+    ];
+
+    #[cfg(feature = "put")]
+    let routes = {
+        let mut routes = routes;
+        routes.push(Route {
+            method: "put",
+            path: "/",
+            handler: Some(|route| route.to(put)),
+            document: |op| {
+                op.summary("Update the location")
+                    .body::<::footprint_api::ObjectLocation>()
+                    .response_with(200, "Updated", &[])
+            },
+        });
+        routes
+    };
+
+    routes
+}
+
+/// Registers the handlers of [`routes`].
+fn configure(config: &mut ServiceConfig) {
+    for route in routes() {
+        let Some(handler) = route.handler else {
+            continue;
+        };
+        let method = Method::from_bytes(route.method.to_uppercase().as_bytes())
+            .expect("routes should have valid methods");
+        config.service(
+            web::resource(route.path)
+                .guard(guard::Method(method))
+                .route(handler(web::route())),
+        );
+    }
+}
+
+async fn openapi() -> impl Responder {
+    static DOCUMENT: OnceLock<Value> = OnceLock::new();
+
+    HttpResponse::Ok().json(DOCUMENT.get_or_init(|| {
+        OpenApi::new("Footprint Provider", env!("CARGO_PKG_VERSION"))
+            .routes(&routes())
+            .build()
+    }))
+}
+
 #[actix_web::main]
 async fn main() {
     async fn try_main() -> Result<()> {
@@ -38,6 +138,11 @@ async fn main() {
 
         ::footprint_provider_api::register(&prometheus.registry)?;
 
+        #[cfg(feature = "otlp")]
+        ::footprint_provider_api::otlp::try_spawn_from_env()?;
+        #[cfg(feature = "remote-write")]
+        ::footprint_provider_api::remote_write::try_spawn_from_env()?;
+
         match infer::<_, String>("FOOTPRINT_PROVIDER")?.as_str() {
             #[cfg(feature = "dummy")]
             "dummy" => ::footprint_provider_dummy::spawn().await?,
@@ -49,26 +154,12 @@ async fn main() {
         }
 
         // Start web server
-        HttpServer::new(move || {
-            let app = App::new()
-                .wrap(prometheus.clone())
-                .service(index)
-                .service(health);
-
-            #[cfg(feature = "put")]
-            {
-                app.service(put)
-            }
-            #[cfg(not(feature = "put"))]
-            {
-                app
-            }
-        })
-        .bind(addr)
-        .unwrap_or_else(|e| panic!("failed to bind to {addr}: {e}"))
-        .run()
-        .await
-        .map_err(Into::into)
+        HttpServer::new(move || App::new().wrap(prometheus.clone()).configure(configure))
+            .bind(addr)
+            .unwrap_or_else(|e| panic!("failed to bind to {addr}: {e}"))
+            .run()
+            .await
+            .map_err(Into::into)
     }
 
     tracer::init_once();